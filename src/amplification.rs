@@ -0,0 +1,187 @@
+use crate::types::{ Amplification, AmplificationQuery, AmplificationServer };
+use crate::query::{ build_query, nameserver_addresses, udp_exchange, udp_socket, Exchange };
+
+use std::net::{ IpAddr, SocketAddr, UdpSocket };
+use std::time::Duration;
+
+use anyhow::Result;
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+use hickory_resolver::proto::op::Message;
+use hickory_resolver::proto::rr::{ RData, Record, RecordType };
+
+const BURST_SIZE: u16 = 30;
+const BURST_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn measure(server: IpAddr, domain: &str, record_type: RecordType) -> (AmplificationQuery, Option<Message>) {
+    let mut result: AmplificationQuery = AmplificationQuery {
+        record_type: record_type.to_string(),
+        request_size: 0,
+        response_size: 0,
+        factor: 0.0,
+        truncated: false,
+        error: String::new(),
+    };
+
+    let exchange: Result<Exchange> = build_query(domain, record_type, true).and_then(
+        |request: Message| udp_exchange(SocketAddr::new(server, 53), &request)
+    );
+
+    match exchange {
+        Ok(exchange) => {
+            result.request_size = exchange.request_size as u32;
+            result.response_size = exchange.response_size as u32;
+            result.factor = (exchange.response_size as f64) / (exchange.request_size as f64);
+            result.truncated = exchange.response.truncated();
+
+            (result, Some(exchange.response))
+        }
+        Err(e) => {
+            result.error = e.to_string();
+            (result, None)
+        }
+    }
+}
+
+#[doc = "RFC 8482: ANY is answered with a single synthesized HINFO or one RRset instead of everything"]
+fn minimal_any(response: &Message) -> bool {
+    let answers: Vec<&Record> = response
+        .answers()
+        .iter()
+        .filter(|record: &&Record| record.record_type() != RecordType::RRSIG)
+        .collect();
+
+    let rfc8482_hinfo: bool = answers.iter().any(|record: &&Record| {
+        match record.data() {
+            Some(RData::HINFO(hinfo)) => hinfo.cpu().eq_ignore_ascii_case(b"RFC8482"),
+            _ => false,
+        }
+    });
+
+    let mut rrset_types: Vec<RecordType> = answers
+        .iter()
+        .map(|record: &&Record| record.record_type())
+        .collect();
+    rrset_types.sort_by_key(|record_type: &RecordType| u16::from(*record_type));
+    rrset_types.dedup();
+
+    rfc8482_hinfo || rrset_types.len() <= 1 || response.truncated()
+}
+
+#[doc = "Send a burst of identical queries; dropped or truncated (slipped) answers point at response rate limiting"]
+fn rate_limited(server: IpAddr, domain: &str) -> Result<(u32, u32)> {
+    let socket: UdpSocket = udp_socket(SocketAddr::new(server, 53))?;
+    socket.set_read_timeout(Some(BURST_TIMEOUT))?;
+
+    let mut request: Message = build_query(domain, RecordType::DNSKEY, true)?;
+
+    for id in 0..BURST_SIZE {
+        request.set_id(id);
+        socket.send(&request.to_vec()?)?;
+    }
+
+    let mut answered: u32 = 0;
+    let mut truncated: u32 = 0;
+    let mut buffer: [u8; 65535] = [0; 65535];
+
+    while let Ok(size) = socket.recv(&mut buffer) {
+        if let Ok(response) = Message::from_vec(&buffer[..size]) {
+            answered += 1;
+
+            if response.truncated() {
+                truncated += 1;
+            }
+        }
+
+        if answered >= BURST_SIZE as u32 {
+            break;
+        }
+    }
+
+    Ok((answered, truncated))
+}
+
+#[doc = "Rate limiting only shows when the burst drops or truncates the DNSKEY answer a single query got back whole"]
+fn rrl_detected(baseline_truncated: Option<bool>, answered: u32, truncated: u32) -> bool {
+    match baseline_truncated {
+        Some(false) => answered < (BURST_SIZE as u32) || truncated > 0,
+        // No baseline answer, or one already truncated, says nothing about the burst
+        _ => false,
+    }
+}
+
+fn check_server(server: IpAddr, domain: &str) -> AmplificationServer {
+    let mut queries: Vec<AmplificationQuery> = Vec::new();
+    let mut any_minimal: bool = false;
+    let mut baseline_truncated: Option<bool> = None;
+
+    for record_type in [RecordType::ANY, RecordType::DNSKEY, RecordType::TXT] {
+        let (query, response) = measure(server, domain, record_type);
+
+        if record_type == RecordType::ANY {
+            // No answer at all is also a refusal to amplify
+            any_minimal = response.as_ref().map(minimal_any).unwrap_or(true);
+        }
+
+        if record_type == RecordType::DNSKEY {
+            baseline_truncated = response.as_ref().map(|response: &Message| response.truncated());
+        }
+
+        queries.push(query);
+    }
+
+    let max_factor: f64 = queries
+        .iter()
+        .map(|query: &AmplificationQuery| query.factor)
+        .fold(0.0, f64::max);
+
+    let burst: Option<(u32, u32)> = rate_limited(server, domain).ok();
+    let (burst_answered, burst_truncated): (u32, u32) = burst.unwrap_or((0, 0));
+
+    AmplificationServer {
+        ip: server.to_string(),
+        queries,
+        max_factor,
+        any_minimal,
+        burst_sent: BURST_SIZE as u32,
+        burst_answered,
+        burst_truncated,
+        rrl_active: burst.is_some() && rrl_detected(baseline_truncated, burst_answered, burst_truncated),
+    }
+}
+
+pub fn check_amplification(domain: &str) -> Result<Amplification> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let servers: Vec<AmplificationServer> = nameserver_addresses(&resolver, domain)?
+        .into_iter()
+        .map(|server: IpAddr| check_server(server, domain))
+        .collect();
+
+    Ok(Amplification {
+        max_factor: servers
+            .iter()
+            .map(|server: &AmplificationServer| server.max_factor)
+            .fold(0.0, f64::max),
+        any_minimal: servers.iter().all(|server: &AmplificationServer| server.any_minimal),
+        rrl_active: servers.iter().all(|server: &AmplificationServer| server.rrl_active),
+        servers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ rrl_detected, BURST_SIZE };
+
+    #[test]
+    fn rrl_needs_a_whole_baseline_answer() {
+        let sent: u32 = BURST_SIZE as u32;
+
+        assert!(!rrl_detected(Some(false), sent, 0));
+        assert!(rrl_detected(Some(false), sent, 3));
+        assert!(rrl_detected(Some(false), sent - 5, 0));
+        assert!(rrl_detected(Some(false), 0, 0));
+        // DNSKEY too large for UDP is truncated with or without rate limiting
+        assert!(!rrl_detected(Some(true), sent, sent));
+        assert!(!rrl_detected(None, 0, 0));
+    }
+}
@@ -0,0 +1,455 @@
+use crate::types::{ Autoconfig, AutoconfigDocument, AutoconfigServer, CertificateChain };
+use crate::certificate::check_chain;
+use crate::kex::check_key_exchange;
+use crate::http::{ https_request, request, resolve, HttpResponse, HttpsResponse };
+use crate::query::lookup_rdata;
+use crate::tls::connect;
+
+use std::net::{ SocketAddr, TcpStream };
+
+use anyhow::{ Error, Result };
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+use hickory_resolver::proto::rr::{ RData, RecordType };
+use roxmltree::{ Document, Node };
+
+pub const AUTOCONFIG_PATH: &str = "/mail/config-v1.1.xml";
+pub const AUTODISCOVER_PATH: &str = "/autodiscover/autodiscover.xml";
+const AUTODISCOVER_SCHEMA: &str = "http://schemas.microsoft.com/exchange/autodiscover/outlook/responseschema/2006a";
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child: &Node| child.tag_name().name().eq_ignore_ascii_case(name))
+        .and_then(|child: Node| child.text())
+        .map(|text: &str| text.trim())
+}
+
+fn server(
+    source: &str,
+    protocol: &str,
+    hostname: &str,
+    port: &str,
+    socket_type: &str,
+    authentication: Vec<String>
+) -> AutoconfigServer {
+    AutoconfigServer {
+        source: source.to_string(),
+        protocol: protocol.to_lowercase(),
+        hostname: hostname.to_string(),
+        port: port.parse().unwrap_or(0),
+        plaintext: socket_type.eq_ignore_ascii_case("plain"),
+        socket_type: socket_type.to_string(),
+        authentication,
+    }
+}
+
+#[doc = "incomingServer and outgoingServer entries of a Thunderbird config-v1.1.xml, placeholders filled in for domain"]
+pub fn parse_autoconfig(xml: &str, domain: &str, source: &str) -> Result<Vec<AutoconfigServer>> {
+    let document: Document = Document::parse(xml)?;
+    let fill = |value: &str| -> String {
+        value
+            .replace("%EMAILDOMAIN%", domain)
+            .replace("%EMAILADDRESS%", &format!("info@{}", domain))
+            .replace("%EMAILLOCALPART%", "info")
+    };
+
+    Ok(
+        document
+            .descendants()
+            .filter(|node: &Node| {
+                let name: &str = node.tag_name().name();
+                name == "incomingServer" || name == "outgoingServer"
+            })
+            .map(|node: Node| {
+                server(
+                    source,
+                    node.attribute("type").unwrap_or(""),
+                    &fill(child_text(node, "hostname").unwrap_or("")),
+                    child_text(node, "port").unwrap_or(""),
+                    child_text(node, "socketType").unwrap_or(""),
+                    node
+                        .children()
+                        .filter(|child: &Node| child.tag_name().name() == "authentication")
+                        .filter_map(|child: Node| child.text())
+                        .map(|text: &str| text.trim().to_string())
+                        .collect()
+                )
+            })
+            .collect()
+    )
+}
+
+#[doc = "Protocol entries of an Outlook Autodiscover (POX) response; Encryption wins over SSL, which defaults to on"]
+pub fn parse_autodiscover(xml: &str, source: &str) -> Result<Vec<AutoconfigServer>> {
+    let document: Document = Document::parse(xml)?;
+
+    Ok(
+        document
+            .descendants()
+            .filter(|node: &Node| node.tag_name().name() == "Protocol")
+            .filter_map(|node: Node| {
+                let protocol: &str = child_text(node, "Type")?;
+
+                if !["IMAP", "POP3", "SMTP"].contains(&protocol.to_uppercase().as_str()) {
+                    return None;
+                }
+
+                let socket_type: &str = match (child_text(node, "Encryption"), child_text(node, "SSL")) {
+                    (Some(encryption), _) if encryption.eq_ignore_ascii_case("none") => "plain",
+                    (Some(encryption), _) if encryption.eq_ignore_ascii_case("tls") => "STARTTLS",
+                    (Some(encryption), _) if encryption.eq_ignore_ascii_case("ssl") => "SSL",
+                    (Some(encryption), _) if encryption.eq_ignore_ascii_case("auto") => "auto",
+                    (_, Some(ssl)) if ssl.eq_ignore_ascii_case("off") => "plain",
+                    _ => "SSL",
+                };
+
+                let authentication: Vec<String> = match child_text(node, "SPA") {
+                    Some(spa) if spa.eq_ignore_ascii_case("on") => vec!["SPA".to_string()],
+                    _ => Vec::new(),
+                };
+
+                Some(
+                    server(
+                        source,
+                        protocol,
+                        child_text(node, "Server").unwrap_or(""),
+                        child_text(node, "Port").unwrap_or(""),
+                        socket_type,
+                        authentication
+                    )
+                )
+            })
+            .collect()
+    )
+}
+
+fn document(url: String) -> AutoconfigDocument {
+    AutoconfigDocument {
+        url,
+        https: false,
+        fetched: false,
+        http_status: 0,
+        certificate: None,
+        certificate_valid: false,
+        key_exchange: None,
+        error: String::new(),
+    }
+}
+
+#[doc = "Fetch over HTTPS at addr, or over plain HTTP on http_port of the same address when HTTPS fails; the body is returned on HTTP 200 only"]
+fn fetch(
+    document: &mut AutoconfigDocument,
+    host: &str,
+    addr: Result<SocketAddr>,
+    http_port: Option<u16>,
+    method: &str,
+    path: &str,
+    body: &[u8]
+) -> Option<String> {
+    let headers: [(&str, &str); 1] = [("Content-Type", "text/xml")];
+    let headers: &[(&str, &str)] = if method == "POST" { &headers } else { &[] };
+
+    let fetched: Result<HttpResponse> = addr.and_then(|addr: SocketAddr| {
+        https_request(addr, method, host, path, headers, body)
+            .map(|fetched: HttpsResponse| {
+                let certificate: CertificateChain = check_chain(&addr.ip().to_string(), host, &fetched.chain);
+                document.https = true;
+                document.certificate_valid = certificate.trusted && certificate.hostname_match;
+                document.certificate = Some(certificate);
+                fetched.response
+            })
+            .or_else(|e: Error| {
+                let port: u16 = match http_port {
+                    Some(port) => port,
+                    None => return Err(e),
+                };
+
+                document.url = document.url.replacen("https://", "http://", 1);
+                let mut stream: TcpStream = connect(SocketAddr::new(addr.ip(), port))?;
+                request(&mut stream, method, host, path, headers, body)
+            })
+    });
+
+    match fetched {
+        Ok(response) => {
+            document.http_status = response.status;
+
+            if response.status != 200 {
+                document.error = format!("HTTP {}", response.status);
+                return None;
+            }
+
+            document.fetched = true;
+            Some(String::from_utf8_lossy(&response.body).to_string())
+        }
+        Err(e) => {
+            document.error = e.to_string();
+            None
+        }
+    }
+}
+
+fn key_exchange(document: &mut AutoconfigDocument, host: &str, addr: Option<SocketAddr>) {
+    if let (true, Some(addr)) = (document.https, addr) {
+        document.key_exchange = Some(check_key_exchange(host, &(|| connect(addr))));
+    }
+}
+
+fn autodiscover_request(domain: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?><Autodiscover xmlns=\"http://schemas.microsoft.com/exchange/autodiscover/outlook/requestschema/2006\"><Request><EMailAddress>info@{}</EMailAddress><AcceptableResponseSchema>{}</AcceptableResponseSchema></Request></Autodiscover>",
+        domain,
+        AUTODISCOVER_SCHEMA
+    )
+}
+
+pub fn check_autoconfig(domain: &str) -> Result<Autoconfig> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let domain: &str = domain.trim_end_matches('.');
+
+    let mut autoconfig: Autoconfig = Autoconfig {
+        srv_records: Vec::new(),
+        documents: Vec::new(),
+        servers: Vec::new(),
+        plaintext: false,
+        error: String::new(),
+    };
+
+    // Thunderbird autoconfig, which clients also try over plain HTTP
+    let host: String = format!("autoconfig.{}", domain);
+    let path: String = format!("{}?emailaddress=info@{}", AUTOCONFIG_PATH, domain);
+    let mut config: AutoconfigDocument = document(format!("https://{}{}", host, path));
+
+    let addr: Result<SocketAddr> = resolve(&host, 443);
+    let https: Option<SocketAddr> = addr.as_ref().ok().copied();
+
+    if let Some(xml) = fetch(&mut config, &host, addr, Some(80), "GET", &path, &[]) {
+        match parse_autoconfig(&xml, domain, &config.url) {
+            Ok(servers) => autoconfig.servers.extend(servers),
+            Err(e) => {
+                config.error = format!("Invalid XML: {}", e);
+            }
+        }
+    }
+    key_exchange(&mut config, &host, https);
+    autoconfig.documents.push(config);
+
+    // Outlook Autodiscover at the SRV target first, then autodiscover.<domain>
+    // A failed SRV lookup only loses that endpoint, the other documents are still fetched
+    let srv: Vec<RData> = match lookup_rdata(&resolver, &format!("_autodiscover._tcp.{}", domain), RecordType::SRV) {
+        Ok(srv) => srv,
+        Err(e) => {
+            autoconfig.error = format!("_autodiscover._tcp SRV lookup failed: {}", e);
+            Vec::new()
+        }
+    };
+    let mut endpoints: Vec<(String, u16)> = srv
+        .iter()
+        .filter_map(|rdata: &RData| {
+            match rdata {
+                RData::SRV(srv) if srv.target().to_string() != "." =>
+                    Some((srv.target().to_string().trim_end_matches('.').to_string(), srv.port())),
+                _ => None,
+            }
+        })
+        .collect();
+    autoconfig.srv_records = endpoints
+        .iter()
+        .map(|(target, port)| format!("{}:{}", target, port))
+        .collect();
+    endpoints.push((format!("autodiscover.{}", domain), 443));
+
+    let body: String = autodiscover_request(domain);
+
+    for (host, port) in endpoints {
+        let mut discovered: AutoconfigDocument = document(format!("https://{}:{}{}", host, port, AUTODISCOVER_PATH));
+
+        let addr: Result<SocketAddr> = resolve(&host, port);
+        let https: Option<SocketAddr> = addr.as_ref().ok().copied();
+
+        if let Some(xml) = fetch(&mut discovered, &host, addr, None, "POST", AUTODISCOVER_PATH, body.as_bytes()) {
+            match parse_autodiscover(&xml, &discovered.url) {
+                Ok(servers) => autoconfig.servers.extend(servers),
+                Err(e) => {
+                    discovered.error = format!("Invalid XML: {}", e);
+                }
+            }
+        }
+        key_exchange(&mut discovered, &host, https);
+        autoconfig.documents.push(discovered);
+    }
+
+    autoconfig.plaintext =
+        autoconfig.servers.iter().any(|server: &AutoconfigServer| server.plaintext) ||
+        autoconfig.documents.iter().any(|document: &AutoconfigDocument| document.fetched && !document.https);
+
+    Ok(autoconfig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ document, fetch, parse_autoconfig, parse_autodiscover };
+    use crate::types::AutoconfigDocument;
+    use crate::test_support::stand_in;
+
+    use std::io::{ Read, Write };
+    use std::net::{ SocketAddr, TcpListener };
+    use std::thread;
+
+    const CONFIG: &str = "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\n\r\n<clientConfig version=\"1.1\"/>";
+
+    #[doc = "One-shot plain HTTP server that answers any request with response"]
+    fn http_stand_in(response: &'static str) -> u16 {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port: u16 = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request: [u8; 1024] = [0; 1024];
+            let _ = stream.read(&mut request);
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        port
+    }
+
+    fn closed_port() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    #[test]
+    fn parses_thunderbird_config() {
+        let servers = parse_autoconfig(
+            r#"<?xml version="1.0"?>
+            <clientConfig version="1.1">
+              <emailProvider id="example.com">
+                <incomingServer type="imap">
+                  <hostname>imap.%EMAILDOMAIN%</hostname>
+                  <port>993</port>
+                  <socketType>SSL</socketType>
+                  <authentication>password-cleartext</authentication>
+                </incomingServer>
+                <incomingServer type="pop3">
+                  <hostname>pop.example.com</hostname>
+                  <port>110</port>
+                  <socketType>plain</socketType>
+                </incomingServer>
+                <outgoingServer type="smtp">
+                  <hostname>smtp.example.com</hostname>
+                  <port>587</port>
+                  <socketType>STARTTLS</socketType>
+                </outgoingServer>
+              </emailProvider>
+            </clientConfig>"#,
+            "example.com",
+            "https://autoconfig.example.com/mail/config-v1.1.xml"
+        ).unwrap();
+
+        assert_eq!(servers.len(), 3);
+        assert_eq!(servers[0].hostname, "imap.example.com");
+        assert_eq!(servers[0].authentication, vec!["password-cleartext"]);
+        assert!(!servers[0].plaintext);
+        assert!(servers[1].plaintext);
+        assert_eq!(servers[2].protocol, "smtp");
+        assert_eq!(servers[2].port, 587);
+    }
+
+    #[test]
+    fn parses_autodiscover_response() {
+        let servers = parse_autodiscover(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <Autodiscover xmlns="http://schemas.microsoft.com/exchange/autodiscover/responseschema/2006">
+              <Response xmlns="http://schemas.microsoft.com/exchange/autodiscover/outlook/responseschema/2006a">
+                <Account>
+                  <Protocol><Type>IMAP</Type><Server>imap.example.com</Server><Port>993</Port><SSL>on</SSL></Protocol>
+                  <Protocol><Type>SMTP</Type><Server>smtp.example.com</Server><Port>25</Port><SSL>off</SSL></Protocol>
+                  <Protocol><Type>SMTP</Type><Server>smtp.example.com</Server><Port>587</Port><Encryption>TLS</Encryption></Protocol>
+                  <Protocol><Type>EXCH</Type><Server>exchange.example.com</Server></Protocol>
+                </Account>
+              </Response>
+            </Autodiscover>"#,
+            "https://autodiscover.example.com/autodiscover/autodiscover.xml"
+        ).unwrap();
+
+        assert_eq!(servers.len(), 3);
+        assert_eq!(servers[0].socket_type, "SSL");
+        assert!(servers[1].plaintext);
+        assert_eq!(servers[2].socket_type, "STARTTLS");
+        assert!(parse_autodiscover("<not xml", "").is_err());
+    }
+
+    #[test]
+    fn fetches_over_https() {
+        let addr: SocketAddr = stand_in("autoconfig.example.test", CONFIG);
+        let mut config: AutoconfigDocument = document("https://autoconfig.example.test/mail/config-v1.1.xml".to_string());
+
+        let xml = fetch(
+            &mut config,
+            "autoconfig.example.test",
+            Ok(addr),
+            Some(closed_port().port()),
+            "GET",
+            "/mail/config-v1.1.xml",
+            &[]
+        );
+
+        assert_eq!(xml.as_deref(), Some("<clientConfig version=\"1.1\"/>"), "{}", config.error);
+        assert!(config.fetched && config.https);
+        assert_eq!(config.http_status, 200);
+        assert!(config.url.starts_with("https://"));
+        assert!(config.certificate.is_some());
+        assert!(!config.certificate_valid);
+    }
+
+    #[test]
+    fn falls_back_to_http() {
+        let port: u16 = http_stand_in(CONFIG);
+        let mut config: AutoconfigDocument = document("https://autoconfig.example.test/mail/config-v1.1.xml".to_string());
+
+        let xml = fetch(
+            &mut config,
+            "autoconfig.example.test",
+            Ok(closed_port()),
+            Some(port),
+            "GET",
+            "/mail/config-v1.1.xml",
+            &[]
+        );
+
+        assert!(xml.is_some(), "{}", config.error);
+        assert!(config.fetched && !config.https);
+        assert!(config.certificate.is_none());
+        assert_eq!(config.url, "http://autoconfig.example.test/mail/config-v1.1.xml");
+    }
+
+    #[test]
+    fn no_http_fallback_unless_allowed() {
+        let mut discovered: AutoconfigDocument = document("https://autodiscover.example.test/autodiscover/autodiscover.xml".to_string());
+
+        let xml = fetch(
+            &mut discovered,
+            "autodiscover.example.test",
+            Ok(closed_port()),
+            None,
+            "POST",
+            "/autodiscover/autodiscover.xml",
+            b"<Autodiscover/>"
+        );
+
+        assert!(xml.is_none());
+        assert!(!discovered.fetched && !discovered.https);
+        assert!(!discovered.error.is_empty());
+        assert!(discovered.url.starts_with("https://"));
+    }
+
+    #[test]
+    fn reports_non_200_status() {
+        let addr: SocketAddr = stand_in("autoconfig.example.test", "HTTP/1.1 404 Not Found\r\n\r\n");
+        let mut config: AutoconfigDocument = document("https://autoconfig.example.test/mail/config-v1.1.xml".to_string());
+
+        assert!(fetch(&mut config, "autoconfig.example.test", Ok(addr), None, "GET", "/", &[]).is_none());
+        assert!(!config.fetched && config.https);
+        assert_eq!(config.http_status, 404);
+        assert_eq!(config.error, "HTTP 404");
+    }
+}
@@ -0,0 +1,372 @@
+use crate::types::{ Bimi, CertificateChain, Dmarc };
+use crate::certificate::check_chain;
+use crate::http::{ https_get, resolve, HttpsResponse };
+use crate::kex::check_key_exchange;
+use crate::psl::organizational_domain;
+use crate::query::txt_records;
+use crate::tls::connect;
+
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+use roxmltree::{ Document, Node, ParsingOptions };
+
+const MAX_LOGO_SIZE: usize = 32 * 1024;
+const PROHIBITED_ELEMENTS: [&str; 12] = [
+    "script",
+    "foreignObject",
+    "image",
+    "a",
+    "animate",
+    "animateColor",
+    "animateMotion",
+    "animateTransform",
+    "set",
+    "video",
+    "audio",
+    "iframe",
+];
+
+#[doc = "Host, port and path of an https URL"]
+fn split_url(url: &str) -> Option<(String, u16, String)> {
+    let rest: &str = url.get(..8).filter(|scheme: &&str| scheme.eq_ignore_ascii_case("https://")).map(|_scheme| &url[8..])?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, 443),
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+
+    Some((host.to_string(), port, path.to_string()))
+}
+
+#[doc = "Parse default._bimi TXT records: v=BIMI1 first, l= an https logo URL (empty declines), a= an https evidence document"]
+pub fn parse_bimi(records: Vec<String>) -> Bimi {
+    let records: Vec<String> = records
+        .into_iter()
+        .filter(|record: &String| record.starts_with("v=BIMI1"))
+        .collect();
+
+    let mut bimi: Bimi = Bimi {
+        name: String::new(),
+        multiple_records: records.len() > 1,
+        records,
+        record_valid: false,
+        declined: false,
+        logo_url: String::new(),
+        authority_url: String::new(),
+        logo_fetched: false,
+        content_type: String::new(),
+        logo_size: 0,
+        svg_valid: false,
+        svg_errors: Vec::new(),
+        certificate: None,
+        certificate_valid: false,
+        key_exchange: None,
+        dmarc_enforced: false,
+        valid: false,
+        errors: Vec::new(),
+        error: String::new(),
+    };
+
+    if bimi.multiple_records {
+        bimi.errors.push("Multiple v=BIMI1 records".to_string());
+    }
+
+    let record: String = match bimi.records.first() {
+        Some(record) => record.clone(),
+        None => {
+            return bimi;
+        }
+    };
+
+    let mut fields = record.split(';').map(|field: &str| field.trim());
+
+    if fields.next() != Some("v=BIMI1") {
+        bimi.errors.push("Record must start with v=BIMI1".to_string());
+    }
+
+    for field in fields.filter(|field: &&str| !field.is_empty()) {
+        match field.split_once('=').map(|(tag, value)| (tag.trim(), value.trim())) {
+            Some(("l", value)) => {
+                bimi.logo_url = value.to_string();
+            }
+            Some(("a", value)) => {
+                bimi.authority_url = value.to_string();
+            }
+            Some(_) => {}
+            None => {
+                bimi.errors.push(format!("Malformed tag {}", field));
+            }
+        }
+    }
+
+    bimi.declined = bimi.logo_url.is_empty() && bimi.authority_url.is_empty();
+
+    if !bimi.logo_url.is_empty() && split_url(&bimi.logo_url).is_none() {
+        bimi.errors.push(format!("l must be an https URL, not {}", bimi.logo_url));
+    }
+    if !bimi.authority_url.is_empty() && split_url(&bimi.authority_url).is_none() {
+        bimi.errors.push(format!("a must be an https URL, not {}", bimi.authority_url));
+    }
+
+    bimi.record_valid = bimi.errors.is_empty();
+
+    bimi
+}
+
+#[doc = "SVG Tiny Portable/Secure: svg root with baseProfile tiny-ps and version 1.2, a title, no x or y, no scripts, animation, links or external references"]
+pub fn validate_svg(svg: &str) -> Vec<String> {
+    let mut errors: Vec<String> = Vec::new();
+
+    if svg.len() > MAX_LOGO_SIZE {
+        errors.push(format!("Logo is {} bytes, above the {} byte limit", svg.len(), MAX_LOGO_SIZE));
+    }
+
+    let options: ParsingOptions = ParsingOptions { allow_dtd: true, ..ParsingOptions::default() };
+    let document: Document = match Document::parse_with_options(svg, options) {
+        Ok(document) => document,
+        Err(e) => {
+            errors.push(format!("Invalid XML: {}", e));
+            return errors;
+        }
+    };
+
+    let root: Node = document.root_element();
+
+    if root.tag_name().name() != "svg" {
+        errors.push("Root element is not svg".to_string());
+        return errors;
+    }
+    if root.attribute("baseProfile") != Some("tiny-ps") {
+        errors.push("baseProfile must be tiny-ps".to_string());
+    }
+    if root.attribute("version") != Some("1.2") {
+        errors.push("version must be 1.2".to_string());
+    }
+    if root.attribute("x").is_some() || root.attribute("y").is_some() {
+        errors.push("svg must not have x or y attributes".to_string());
+    }
+    if !root.children().any(|child: Node| child.tag_name().name() == "title") {
+        errors.push("svg must have a title element".to_string());
+    }
+
+    for node in root.descendants().filter(|node: &Node| node.is_element()) {
+        let name: &str = node.tag_name().name();
+
+        if PROHIBITED_ELEMENTS.contains(&name) {
+            errors.push(format!("Prohibited element {}", name));
+        }
+
+        for attribute in node.attributes() {
+            let attribute_name: &str = attribute.name();
+
+            if attribute_name.starts_with("on") {
+                errors.push(format!("Event handler {} on {}", attribute_name, name));
+            }
+            if attribute_name == "href" && !attribute.value().starts_with('#') {
+                errors.push(format!("External reference {}", attribute.value()));
+            }
+        }
+    }
+
+    errors.dedup();
+
+    errors
+}
+
+#[doc = "Fetch the logo from addr and validate it as SVG Tiny PS"]
+pub fn fetch_logo(bimi: &mut Bimi, addr: SocketAddr, host: &str, path: &str) {
+    let fetched: HttpsResponse = match https_get(addr, host, path) {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            bimi.errors.push(format!("Logo fetch failed: {}", e));
+            return;
+        }
+    };
+
+    if fetched.response.status != 200 {
+        bimi.errors.push(format!("Logo answered HTTP {}", fetched.response.status));
+        return;
+    }
+
+    bimi.logo_fetched = true;
+
+    let certificate: CertificateChain = check_chain(&addr.ip().to_string(), host, &fetched.chain);
+    bimi.certificate_valid = certificate.trusted && certificate.hostname_match;
+    bimi.certificate = Some(certificate);
+
+    if !bimi.certificate_valid {
+        bimi.errors.push(format!("Logo certificate is not valid for {}", host));
+    }
+
+    bimi.content_type = fetched.response.header("Content-Type").unwrap_or("").to_string();
+
+    // Parameters such as charset may follow the media type
+    let media_type: &str = bimi.content_type.split(';').next().unwrap_or("").trim();
+    if !media_type.eq_ignore_ascii_case("image/svg+xml") {
+        bimi.errors.push(format!("Logo Content-Type is {}, not image/svg+xml", bimi.content_type));
+    }
+
+    bimi.logo_size = fetched.response.body.len() as u32;
+    bimi.svg_errors = validate_svg(&String::from_utf8_lossy(&fetched.response.body));
+    bimi.svg_valid = bimi.svg_errors.is_empty();
+}
+
+fn lookup_bimi(domain: &str) -> Result<(String, Vec<String>)> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let organizational: String = organizational_domain(domain);
+
+    let name: String = format!("default._bimi.{}", domain);
+    let records: Vec<String> = txt_records(&resolver, &name)?;
+
+    if organizational != *domain && !records.iter().any(|record: &String| record.starts_with("v=BIMI1")) {
+        let name: String = format!("default._bimi.{}", organizational);
+        let records: Vec<String> = txt_records(&resolver, &name)?;
+        return Ok((name, records));
+    }
+
+    Ok((name, records))
+}
+
+#[doc = "BIMI at default._bimi of the domain or else its organizational domain; a logo needs an enforcing DMARC policy. A failed lookup is reported in error"]
+pub fn check_bimi(domain: &str, dmarc: &Dmarc) -> Bimi {
+    let domain: String = domain.trim_end_matches('.').to_lowercase();
+
+    let mut bimi: Bimi = match lookup_bimi(&domain) {
+        Ok((name, records)) => {
+            let mut bimi: Bimi = parse_bimi(records);
+            bimi.name = name;
+            bimi
+        }
+        Err(e) => {
+            let mut bimi: Bimi = parse_bimi(Vec::new());
+            bimi.name = format!("default._bimi.{}", domain);
+            bimi.error = e.to_string();
+            bimi
+        }
+    };
+
+    // Quarantine or reject at pct=100, and sp must not weaken it to none
+    bimi.dmarc_enforced = dmarc.enforced && dmarc.subdomain_policy != "none";
+
+    if bimi.records.is_empty() {
+        return bimi;
+    }
+
+    if !bimi.dmarc_enforced {
+        bimi.errors.push("DMARC policy is not enforcing".to_string());
+    }
+
+    if let Some((host, port, path)) = split_url(&bimi.logo_url) {
+        match resolve(&host, port) {
+            Ok(addr) => {
+                fetch_logo(&mut bimi, addr, &host, &path);
+
+                if bimi.certificate.is_some() {
+                    bimi.key_exchange = Some(check_key_exchange(&host, &(|| connect(addr))));
+                }
+            }
+            Err(e) => {
+                bimi.errors.push(format!("Logo fetch failed: {}", e));
+            }
+        }
+    }
+
+    bimi.valid = bimi.record_valid && !bimi.declined && bimi.svg_valid && bimi.dmarc_enforced && bimi.errors.is_empty();
+
+    bimi
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ fetch_logo, parse_bimi, split_url, validate_svg };
+    use crate::test_support::stand_in;
+
+    use std::net::SocketAddr;
+
+    const LOGO: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" version="1.2" baseProfile="tiny-ps" viewBox="0 0 100 100">
+  <title>Example</title>
+  <rect width="100" height="100" fill="#0a5"/>
+</svg>"##;
+
+    #[test]
+    fn parses_record() {
+        let bimi = parse_bimi(
+            vec!["v=BIMI1; l=https://example.com/logo.svg; a=https://example.com/vmc.pem".to_string()]
+        );
+        assert!(bimi.record_valid);
+        assert!(!bimi.declined);
+        assert_eq!(split_url(&bimi.logo_url), Some(("example.com".to_string(), 443, "/logo.svg".to_string())));
+
+        let bimi = parse_bimi(vec!["v=BIMI1; l=; a=;".to_string()]);
+        assert!(bimi.declined);
+
+        let bimi = parse_bimi(vec!["v=BIMI1; l=http://example.com/logo.svg".to_string()]);
+        assert!(!bimi.record_valid);
+    }
+
+    #[test]
+    fn validates_svg_tiny_ps() {
+        assert!(validate_svg(LOGO).is_empty(), "{:?}", validate_svg(LOGO));
+
+        let errors = validate_svg(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" version="1.1" x="0">
+  <script>alert(1)</script>
+  <image xlink:href="https://example.com/logo.png" onload="alert(1)"/>
+</svg>"#
+        );
+        for expected in [
+            "baseProfile must be tiny-ps",
+            "version must be 1.2",
+            "svg must not have x or y attributes",
+            "svg must have a title element",
+            "Prohibited element script",
+            "Prohibited element image",
+            "Event handler onload on image",
+            "External reference https://example.com/logo.png",
+        ] {
+            assert!(errors.contains(&expected.to_string()), "{} missing from {:?}", expected, errors);
+        }
+    }
+
+    #[test]
+    fn fetches_logo_from_stand_in() {
+        let addr: SocketAddr = stand_in(
+            "bimi.example.test",
+            "HTTP/1.1 200 OK\r\nContent-Type: image/svg+xml\r\n\r\n<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.2\" baseProfile=\"tiny-ps\"><title>Example</title></svg>"
+        );
+        let mut bimi = parse_bimi(vec!["v=BIMI1; l=https://bimi.example.test/logo.svg".to_string()]);
+
+        fetch_logo(&mut bimi, addr, "bimi.example.test", "/logo.svg");
+
+        assert!(bimi.logo_fetched, "{:?}", bimi.errors);
+        assert_eq!(bimi.content_type, "image/svg+xml");
+        assert!(bimi.svg_valid, "{:?}", bimi.svg_errors);
+        // The stand-in certificate is self-signed
+        assert!(!bimi.certificate_valid);
+        assert_eq!(bimi.errors, vec!["Logo certificate is not valid for bimi.example.test"]);
+    }
+
+    #[test]
+    fn rejects_logo_served_with_other_content_type() {
+        let addr: SocketAddr = stand_in(
+            "bimi.example.test",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\r\n<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.2\" baseProfile=\"tiny-ps\"><title>Example</title></svg>"
+        );
+        let mut bimi = parse_bimi(vec!["v=BIMI1; l=https://bimi.example.test/logo.svg".to_string()]);
+
+        fetch_logo(&mut bimi, addr, "bimi.example.test", "/logo.svg");
+
+        assert!(bimi.errors.contains(&"Logo Content-Type is text/html; charset=utf-8, not image/svg+xml".to_string()));
+    }
+}
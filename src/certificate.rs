@@ -0,0 +1,232 @@
+use crate::types::{ Certificate, CertificateChain };
+use crate::tls::{ name_to_string, subject };
+
+use std::env::var;
+use std::fs::read;
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::{ Result, Error };
+use openssl::asn1::{ Asn1Time, Asn1TimeRef };
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::Id;
+use openssl::ssl::SslFiletype;
+use openssl::stack::Stack;
+use openssl::x509::{ X509, X509Ref, X509StoreContext, X509VerifyResult };
+use openssl::x509::store::{ X509Lookup, X509Store, X509StoreBuilder };
+
+#[doc = "Trust store from TRUST_STORE (PEM bundle or hashed certificate directory), the system default otherwise"]
+pub fn trust_store() -> Result<X509Store> {
+    let mut builder: X509StoreBuilder = X509StoreBuilder::new()?;
+
+    match var("TRUST_STORE").ok().filter(|path: &String| !path.is_empty()) {
+        Some(path) if Path::new(&path).is_dir() => {
+            builder.add_lookup(X509Lookup::hash_dir())?.add_dir(&path, SslFiletype::PEM)?;
+        }
+        Some(path) => {
+            for cert in X509::stack_from_pem(&read(&path)?)? {
+                builder.add_cert(cert)?;
+            }
+        }
+        None => {
+            builder.set_default_paths()?;
+        }
+    }
+
+    Ok(builder.build())
+}
+
+fn days_until(time: &Asn1TimeRef) -> i64 {
+    Asn1Time::days_from_now(0)
+        .and_then(|now: Asn1Time| now.diff(time))
+        .map(|diff| diff.days as i64)
+        .unwrap_or(0)
+}
+
+fn key_type(id: Id) -> &'static str {
+    match id {
+        Id::RSA => "RSA",
+        Id::RSA_PSS => "RSA-PSS",
+        Id::EC => "EC",
+        Id::DSA => "DSA",
+        Id::ED25519 => "Ed25519",
+        Id::ED448 => "Ed448",
+        _ => "unknown",
+    }
+}
+
+fn ip_to_string(bytes: &[u8]) -> String {
+    if let Ok(octets) = <[u8; 4]>::try_from(bytes) {
+        return IpAddr::from(octets).to_string();
+    }
+    if let Ok(octets) = <[u8; 16]>::try_from(bytes) {
+        return IpAddr::from(octets).to_string();
+    }
+
+    String::new()
+}
+
+pub fn subject_alt_names(cert: &X509Ref) -> Vec<String> {
+    cert.subject_alt_names()
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| {
+                    name.dnsname()
+                        .map(|dns: &str| dns.to_string())
+                        .or_else(|| name.ipaddress().map(ip_to_string))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[doc = "RFC 6125 name match: a wildcard only covers the complete left-most label"]
+pub fn name_matches(pattern: &str, hostname: &str) -> bool {
+    let pattern: String = pattern.trim_end_matches('.').to_lowercase();
+    let hostname: String = hostname.trim_end_matches('.').to_lowercase();
+
+    match pattern.strip_prefix("*.") {
+        Some(suffix) =>
+            hostname
+                .split_once('.')
+                .map(|(label, rest)| !label.is_empty() && rest == suffix)
+                .unwrap_or(false),
+        None => pattern == hostname,
+    }
+}
+
+#[doc = "Hostname against the SANs, or the subject CN only when the certificate has no SANs"]
+pub fn hostname_matches(cert: &X509Ref, hostname: &str) -> bool {
+    let sans: Vec<String> = subject_alt_names(cert);
+
+    if !sans.is_empty() {
+        return sans.iter().any(|san: &String| name_matches(san, hostname));
+    }
+
+    cert.subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .filter_map(|entry| entry.data().as_utf8().ok())
+        .any(|cn| name_matches(&cn, hostname))
+}
+
+pub fn certificate(cert: &X509Ref) -> Certificate {
+    let (key_type, key_bits): (String, u32) = match cert.public_key() {
+        Ok(key) => (key_type(key.id()).to_string(), key.bits()),
+        Err(_e) => (String::new(), 0),
+    };
+
+    Certificate {
+        subject: subject(cert),
+        issuer: name_to_string(cert.issuer_name()),
+        sans: subject_alt_names(cert),
+        serial: cert
+            .serial_number()
+            .to_bn()
+            .and_then(|serial| serial.to_hex_str().map(|hex| hex.to_string()))
+            .unwrap_or_default(),
+        not_before: cert.not_before().to_string(),
+        not_after: cert.not_after().to_string(),
+        days_until_expiry: days_until(cert.not_after()),
+        key_type,
+        key_bits,
+        signature_algorithm: cert
+            .signature_algorithm()
+            .object()
+            .nid()
+            .long_name()
+            .unwrap_or("")
+            .to_string(),
+        fingerprint_sha256: cert
+            .digest(MessageDigest::sha256())
+            .map(|digest| {
+                digest
+                    .iter()
+                    .map(|byte: &u8| format!("{:02X}", byte))
+                    .collect::<Vec<String>>()
+                    .join(":")
+            })
+            .unwrap_or_default(),
+    }
+}
+
+fn verify(chain: &[X509]) -> Result<X509VerifyResult> {
+    let leaf: &X509 = chain.first().ok_or_else(|| Error::msg("Empty certificate chain"))?;
+    let store: X509Store = trust_store()?;
+
+    let mut intermediates: Stack<X509> = Stack::new()?;
+    for cert in chain.iter().skip(1) {
+        intermediates.push(cert.clone())?;
+    }
+
+    let mut context: X509StoreContext = X509StoreContext::new()?;
+    let result: X509VerifyResult = context.init(&store, leaf, &intermediates, |context| {
+        context.verify_cert()?;
+        Ok(context.error())
+    })?;
+
+    Ok(result)
+}
+
+#[doc = "Present and validate the chain a server sent, leaf first, against the trust store and the expected hostname"]
+pub fn check_chain(ip: &str, hostname: &str, chain: &[X509]) -> CertificateChain {
+    let mut result: CertificateChain = CertificateChain {
+        ip: ip.to_string(),
+        certificates: chain
+            .iter()
+            .map(|cert: &X509| certificate(cert))
+            .collect(),
+        trusted: false,
+        verify_error: String::new(),
+        hostname_match: false,
+        days_until_expiry: 0,
+        error: String::new(),
+    };
+
+    if let Some(leaf) = chain.first() {
+        result.hostname_match = hostname_matches(leaf, hostname);
+        result.days_until_expiry = days_until(leaf.not_after());
+    }
+
+    match verify(chain) {
+        Ok(verified) if verified == X509VerifyResult::OK => {
+            result.trusted = true;
+        }
+        Ok(verified) => {
+            result.verify_error = verified.error_string().to_string();
+        }
+        Err(e) => {
+            result.error = e.to_string();
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ hostname_matches, name_matches };
+    use crate::test_support::bmp_self_signed;
+
+    use openssl::nid::Nid;
+
+    #[test]
+    fn matches_wildcards_on_one_label() {
+        assert!(name_matches("mx1.example.com", "MX1.example.com."));
+        assert!(name_matches("*.example.com", "mx1.example.com"));
+        assert!(!name_matches("*.example.com", "example.com"));
+        assert!(!name_matches("*.example.com", "a.mx1.example.com"));
+        assert!(!name_matches("mx2.example.com", "mx1.example.com"));
+    }
+
+    #[test]
+    fn matches_cn_in_any_string_type() {
+        let cert = bmp_self_signed("mx.example.test");
+        let cn = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next().unwrap();
+        assert_eq!(cn.data().as_slice().len(), 30);
+
+        assert!(hostname_matches(&cert, "mx.example.test"));
+        assert!(!hostname_matches(&cert, "mx2.example.test"));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::types::{ TlsCipher, TlsScan, TlsVersionSupport };
+use crate::kex::check_key_exchange;
+use crate::tls::{ handshake_with, scan_connector };
+
+use std::net::TcpStream;
+
+use anyhow::{ Result, Error };
+use openssl::ssl::{ SslStream, SslVersion };
+
+pub const ALL_CIPHERS: &str = "ALL:COMPLEMENTOFALL";
+pub const TLS13_CIPHERSUITES: [&str; 5] = [
+    "TLS_AES_256_GCM_SHA384",
+    "TLS_CHACHA20_POLY1305_SHA256",
+    "TLS_AES_128_GCM_SHA256",
+    "TLS_AES_128_CCM_SHA256",
+    "TLS_AES_128_CCM_8_SHA256",
+];
+const MAX_CIPHERS: usize = 128;
+
+// NCSC TLS guidelines, also used by Internet.nl, from best to worst
+pub const GRADES: [&str; 4] = ["good", "sufficient", "phase-out", "insufficient"];
+
+struct Negotiated {
+    version: String,
+    cipher: String,
+    standard_name: String,
+}
+
+fn attempt(
+    open: &dyn Fn() -> Result<TcpStream>,
+    server_name: &str,
+    min: SslVersion,
+    max: SslVersion,
+    cipher_list: &str,
+    ciphersuites: &str
+) -> Result<Negotiated> {
+    let connector = scan_connector(min, max, cipher_list, ciphersuites)?;
+    let stream: SslStream<TcpStream> = handshake_with(&connector, open()?, server_name)?;
+    let cipher = stream
+        .ssl()
+        .current_cipher()
+        .ok_or_else(|| Error::msg("No cipher negotiated"))?;
+
+    Ok(Negotiated {
+        version: stream.ssl().version_str().to_string(),
+        cipher: cipher.name().to_string(),
+        standard_name: cipher.standard_name().unwrap_or("").to_string(),
+    })
+}
+
+pub fn grade_version(version: &str) -> &'static str {
+    match version {
+        "TLSv1.3" => "good",
+        "TLSv1.2" => "sufficient",
+        "TLSv1.1" | "TLSv1" => "phase-out",
+        _ => "insufficient",
+    }
+}
+
+#[doc = "Grade an OpenSSL cipher name by key exchange and bulk cipher"]
+pub fn grade_cipher(name: &str) -> &'static str {
+    let weak: bool = ["NULL", "RC4", "EXP", "MD5", "ADH", "AECDH", "PSK", "SRP"]
+        .iter()
+        .any(|part: &&str| name.contains(part));
+
+    if weak {
+        return "insufficient";
+    }
+
+    // TLS 1.3 suites use the IANA names and always have forward secrecy
+    if name.starts_with("TLS_") {
+        return match name {
+            "TLS_AES_128_CCM_8_SHA256" => "phase-out",
+            "TLS_AES_128_CCM_SHA256" => "sufficient",
+            _ => "good",
+        };
+    }
+
+    let aead: bool = (name.contains("AES") && name.contains("GCM")) || name.contains("CHACHA20");
+    let cbc_or_ccm: bool = name.contains("AES") && !aead && !name.contains("CCM8");
+    let triple_des: bool = name.contains("DES-CBC3");
+
+    if name.starts_with("ECDHE-") {
+        if aead {
+            "good"
+        } else if cbc_or_ccm {
+            "sufficient"
+        } else if triple_des {
+            "phase-out"
+        } else {
+            "insufficient"
+        }
+    } else if name.starts_with("DHE-") || name.starts_with("EDH-") {
+        if aead {
+            "sufficient"
+        } else if cbc_or_ccm || triple_des {
+            "phase-out"
+        } else {
+            "insufficient"
+        }
+    } else if aead || cbc_or_ccm || triple_des {
+        // Static RSA key exchange, no forward secrecy
+        "phase-out"
+    } else {
+        "insufficient"
+    }
+}
+
+pub fn worst_grade<'a>(grades: impl Iterator<Item = &'a str>) -> String {
+    grades
+        .filter_map(|grade: &str| GRADES.iter().position(|known: &&str| *known == grade))
+        .max()
+        .map(|index: usize| GRADES[index].to_string())
+        .unwrap_or_default()
+}
+
+fn cipher(negotiated: Negotiated) -> TlsCipher {
+    TlsCipher {
+        grade: grade_cipher(&negotiated.cipher).to_string(),
+        name: negotiated.cipher,
+        standard_name: negotiated.standard_name,
+        version: negotiated.version,
+    }
+}
+
+#[doc = "Offer every remaining cipher and drop the one chosen, so ciphers come out in the order the server picks them"]
+fn enumerate_legacy(open: &dyn Fn() -> Result<TcpStream>, server_name: &str) -> Vec<TlsCipher> {
+    let mut ciphers: Vec<TlsCipher> = Vec::new();
+    let mut cipher_list: String = ALL_CIPHERS.to_string();
+
+    while ciphers.len() < MAX_CIPHERS {
+        let negotiated: Negotiated = match
+            attempt(open, server_name, SslVersion::TLS1, SslVersion::TLS1_2, &cipher_list, "")
+        {
+            Ok(negotiated) => negotiated,
+            Err(_e) => {
+                break;
+            }
+        };
+
+        if ciphers.iter().any(|known: &TlsCipher| known.name == negotiated.cipher) {
+            break;
+        }
+
+        cipher_list.push_str(&format!(":!{}", negotiated.cipher));
+        ciphers.push(cipher(negotiated));
+    }
+
+    ciphers
+}
+
+fn enumerate_tls13(open: &dyn Fn() -> Result<TcpStream>, server_name: &str) -> Vec<TlsCipher> {
+    let mut ciphers: Vec<TlsCipher> = Vec::new();
+    let mut remaining: Vec<&str> = TLS13_CIPHERSUITES.to_vec();
+
+    while !remaining.is_empty() {
+        let negotiated: Negotiated = match
+            attempt(
+                open,
+                server_name,
+                SslVersion::TLS1_3,
+                SslVersion::TLS1_3,
+                ALL_CIPHERS,
+                &remaining.join(":")
+            )
+        {
+            Ok(negotiated) => negotiated,
+            Err(_e) => {
+                break;
+            }
+        };
+
+        let before: usize = remaining.len();
+        remaining.retain(|suite: &&str| *suite != negotiated.cipher);
+
+        if remaining.len() == before {
+            break;
+        }
+
+        ciphers.push(cipher(negotiated));
+    }
+
+    ciphers
+}
+
+#[doc = "Offer the server's least preferred cipher first; if it still picks its favourite it enforces its own order. Needs at least two legacy ciphers to tell"]
+fn server_preference(
+    open: &dyn Fn() -> Result<TcpStream>,
+    server_name: &str,
+    ciphers: &[TlsCipher]
+) -> bool {
+    let legacy: Vec<&TlsCipher> = ciphers
+        .iter()
+        .filter(|cipher: &&TlsCipher| !cipher.name.starts_with("TLS_"))
+        .collect();
+
+    match (legacy.first(), legacy.last()) {
+        (Some(first), Some(last)) if legacy.len() > 1 => {
+            let reversed: String = format!("{}:{}", last.name, first.name);
+
+            attempt(open, server_name, SslVersion::TLS1, SslVersion::TLS1_2, &reversed, "")
+                .map(|negotiated: Negotiated| negotiated.cipher == first.name)
+                .unwrap_or(false)
+        }
+        _ => false,
+    }
+}
+
+#[doc = "Enumerate TLS versions and ciphers of an endpoint; open returns a fresh TCP stream ready for the TLS handshake"]
+pub fn scan_tls(ip: &str, server_name: &str, open: &dyn Fn() -> Result<TcpStream>) -> TlsScan {
+    let mut versions: Vec<TlsVersionSupport> = Vec::new();
+    let mut error: String = String::new();
+
+    for (version, label) in [
+        (SslVersion::TLS1, "TLSv1"),
+        (SslVersion::TLS1_1, "TLSv1.1"),
+        (SslVersion::TLS1_2, "TLSv1.2"),
+        (SslVersion::TLS1_3, "TLSv1.3"),
+    ] {
+        let suites: String = TLS13_CIPHERSUITES.join(":");
+        let supported: bool = match attempt(open, server_name, version, version, ALL_CIPHERS, &suites) {
+            Ok(_negotiated) => true,
+            Err(e) => {
+                error = e.to_string();
+                false
+            }
+        };
+
+        versions.push(TlsVersionSupport {
+            version: label.to_string(),
+            supported,
+            grade: grade_version(label).to_string(),
+        });
+    }
+
+    let supports = |label: &str| {
+        versions.iter().any(|version: &TlsVersionSupport| version.version == label && version.supported)
+    };
+
+    let mut ciphers: Vec<TlsCipher> = Vec::new();
+
+    if supports("TLSv1.3") {
+        ciphers.extend(enumerate_tls13(open, server_name));
+    }
+    if supports("TLSv1") || supports("TLSv1.1") || supports("TLSv1.2") {
+        ciphers.extend(enumerate_legacy(open, server_name));
+    }
+
+    if !ciphers.is_empty() {
+        error = String::new();
+    }
+
+    let grade: String = worst_grade(
+        versions
+            .iter()
+            .filter(|version: &&TlsVersionSupport| version.supported)
+            .map(|version: &TlsVersionSupport| version.grade.as_str())
+            .chain(ciphers.iter().map(|cipher: &TlsCipher| cipher.grade.as_str()))
+    );
+
+    TlsScan {
+        ip: ip.to_string(),
+        server_preference: server_preference(open, server_name, &ciphers),
+        key_exchange: check_key_exchange(server_name, open),
+        versions,
+        ciphers,
+        grade,
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ grade_cipher, grade_version, server_preference, worst_grade };
+    use crate::types::TlsCipher;
+
+    use anyhow::Error;
+
+    #[test]
+    fn grades_ciphers() {
+        assert_eq!(grade_cipher("TLS_AES_256_GCM_SHA384"), "good");
+        assert_eq!(grade_cipher("ECDHE-RSA-AES256-GCM-SHA384"), "good");
+        assert_eq!(grade_cipher("ECDHE-ECDSA-AES128-SHA"), "sufficient");
+        assert_eq!(grade_cipher("DHE-RSA-CHACHA20-POLY1305"), "sufficient");
+        assert_eq!(grade_cipher("DHE-RSA-AES256-SHA256"), "phase-out");
+        assert_eq!(grade_cipher("AES128-GCM-SHA256"), "phase-out");
+        assert_eq!(grade_cipher("EDH-RSA-DES-CBC3-SHA"), "phase-out");
+        assert_eq!(grade_cipher("ECDHE-RSA-RC4-SHA"), "insufficient");
+        assert_eq!(grade_cipher("ADH-AES256-GCM-SHA384"), "insufficient");
+        assert_eq!(grade_cipher("ECDHE-RSA-CAMELLIA256-SHA384"), "insufficient");
+    }
+
+    #[test]
+    fn picks_worst_grade() {
+        assert_eq!(grade_version("TLSv1.1"), "phase-out");
+        assert_eq!(worst_grade(["good", "phase-out", "sufficient"].into_iter()), "phase-out");
+        assert_eq!(worst_grade(std::iter::empty()), "");
+    }
+
+    #[test]
+    fn no_server_preference_without_two_legacy_ciphers() {
+        let cipher = |name: &str| TlsCipher {
+            name: name.to_string(),
+            standard_name: String::new(),
+            version: "TLSv1.2".to_string(),
+            grade: String::new(),
+        };
+        let unreachable = || Err(Error::msg("no connection expected"));
+
+        assert!(!server_preference(&unreachable, "example.test", &[]));
+        assert!(!server_preference(&unreachable, "example.test", &[cipher("ECDHE-RSA-AES256-GCM-SHA384")]));
+        assert!(
+            !server_preference(
+                &unreachable,
+                "example.test",
+                &[cipher("TLS_AES_256_GCM_SHA384"), cipher("ECDHE-RSA-AES256-GCM-SHA384")]
+            )
+        );
+    }
+}
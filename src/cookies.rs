@@ -0,0 +1,274 @@
+use crate::query::{ build_query, udp_exchange };
+
+use std::net::{ IpAddr, SocketAddr };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use anyhow::Result;
+use hickory_resolver::proto::op::{ Edns, Message, ResponseCode };
+use hickory_resolver::proto::rr::RecordType;
+use hickory_resolver::proto::rr::rdata::opt::{ EdnsCode, EdnsOption };
+
+const COOKIE_CODE: u16 = 10;
+
+#[doc = "RFC 7873 DNS Cookie behaviour of a nameserver address"]
+pub struct CookieSupport {
+    pub server_cookie: bool,
+    pub stable: bool,
+    pub badcookie_handled: bool,
+}
+
+struct CookieResponse {
+    response_code: ResponseCode,
+    client_cookie: Vec<u8>,
+    server_cookie: Vec<u8>,
+}
+
+fn client_cookie() -> Vec<u8> {
+    let nanos: u128 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+
+    (nanos as u64).to_be_bytes().to_vec()
+}
+
+fn send_cookie(server: SocketAddr, domain: &str, cookie: Vec<u8>) -> Result<CookieResponse> {
+    let mut request: Message = build_query(domain, RecordType::SOA, false)?;
+    request
+        .extensions_mut()
+        .get_or_insert_with(Edns::new)
+        .options_mut()
+        .insert(EdnsOption::Unknown(COOKIE_CODE, cookie));
+
+    let response: Message = udp_exchange(server, &request)?.response;
+    let option: Vec<u8> = match response.extensions().as_ref().and_then(|edns| edns.option(EdnsCode::Cookie)) {
+        Some(EdnsOption::Unknown(_, data)) => data.clone(),
+        _ => Vec::new(),
+    };
+
+    Ok(CookieResponse {
+        response_code: response.response_code(),
+        client_cookie: option.get(..8).unwrap_or(&[]).to_vec(),
+        server_cookie: option.get(8..).unwrap_or(&[]).to_vec(),
+    })
+}
+
+#[doc = "RFC 9018 interoperable cookies embed a timestamp and legitimately change between responses"]
+fn interoperable(server_cookie: &[u8]) -> bool {
+    server_cookie.len() == 16 && server_cookie[0] == 1
+}
+
+fn probe_cookies(server: SocketAddr, domain: &str) -> CookieSupport {
+    let mut support: CookieSupport = CookieSupport {
+        server_cookie: false,
+        stable: false,
+        badcookie_handled: false,
+    };
+
+    let client: Vec<u8> = client_cookie();
+
+    let first: CookieResponse = match send_cookie(server, domain, client.clone()) {
+        Ok(response) => response,
+        Err(_e) => {
+            return support;
+        }
+    };
+
+    // Server cookies are 8 to 32 bytes and must come back with our client cookie
+    support.server_cookie =
+        first.client_cookie == client &&
+        (8..=32).contains(&first.server_cookie.len());
+
+    if !support.server_cookie {
+        return support;
+    }
+
+    let mut echoed: Vec<u8> = client.clone();
+    echoed.extend_from_slice(&first.server_cookie);
+
+    if let Ok(second) = send_cookie(server, domain, echoed) {
+        support.stable =
+            second.response_code == ResponseCode::NoError &&
+            (second.server_cookie == first.server_cookie ||
+                (interoperable(&first.server_cookie) && interoperable(&second.server_cookie)));
+    }
+
+    let mut bogus: Vec<u8> = client.clone();
+    bogus.extend_from_slice(&[0xa5; 16]);
+
+    // A bad server cookie is either answered normally or with BADCOOKIE, both carrying a fresh cookie
+    if let Ok(third) = send_cookie(server, domain, bogus) {
+        support.badcookie_handled =
+            (third.response_code == ResponseCode::NoError ||
+                third.response_code == ResponseCode::BADCOOKIE) &&
+            third.client_cookie == client &&
+            (8..=32).contains(&third.server_cookie.len()) &&
+            third.server_cookie != [0xa5; 16];
+    }
+
+    support
+}
+
+pub fn check_cookies(server: IpAddr, domain: &str) -> CookieSupport {
+    probe_cookies(SocketAddr::new(server, 53), domain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ probe_cookies, COOKIE_CODE };
+
+    use std::net::{ SocketAddr, UdpSocket };
+    use std::thread;
+    use std::time::Duration;
+
+    use hickory_resolver::proto::op::{ Edns, Message, MessageType, ResponseCode };
+    use hickory_resolver::proto::rr::rdata::opt::{ EdnsCode, EdnsOption };
+
+    const SERVER_COOKIE: [u8; 8] = [0x5e, 0x7e, 0x11, 0x0c, 0x00, 0x01, 0x02, 0x03];
+
+    #[doc = "UDP nameserver that answers each query with the response code and cookie option of answer; no option when it is empty"]
+    fn stand_in(answer: fn(&[u8], u32) -> (ResponseCode, Vec<u8>)) -> SocketAddr {
+        let socket: UdpSocket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let addr: SocketAddr = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut queries: u32 = 0;
+            let mut buffer: [u8; 4096] = [0; 4096];
+
+            while let Ok((size, peer)) = socket.recv_from(&mut buffer) {
+                let request: Message = Message::from_vec(&buffer[..size]).unwrap();
+                let cookie: Vec<u8> = match request.extensions().as_ref().and_then(|edns| edns.option(EdnsCode::Cookie)) {
+                    Some(EdnsOption::Unknown(_, data)) => data.clone(),
+                    _ => Vec::new(),
+                };
+                let (response_code, option) = answer(&cookie, queries);
+                queries += 1;
+
+                let mut response: Message = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_response_code(response_code)
+                    .add_queries(request.queries().to_vec());
+
+                let mut edns: Edns = Edns::new();
+                if !option.is_empty() {
+                    edns.options_mut().insert(EdnsOption::Unknown(COOKIE_CODE, option));
+                }
+                response.set_edns(edns);
+
+                socket.send_to(&response.to_vec().unwrap(), peer).unwrap();
+            }
+        });
+
+        addr
+    }
+
+    fn with_server_cookie(request: &[u8], server_cookie: &[u8]) -> Vec<u8> {
+        let mut option: Vec<u8> = request[..8].to_vec();
+        option.extend_from_slice(server_cookie);
+        option
+    }
+
+    #[doc = "Fixed server cookie; a wrong one is answered with BADCOOKIE and the right cookie"]
+    fn stable(request: &[u8], _query: u32) -> (ResponseCode, Vec<u8>) {
+        let response_code: ResponseCode = match request.get(8..) {
+            Some(server) if !server.is_empty() && server != SERVER_COOKIE => ResponseCode::BADCOOKIE,
+            _ => ResponseCode::NoError,
+        };
+
+        (response_code, with_server_cookie(request, &SERVER_COOKIE))
+    }
+
+    #[test]
+    fn supports_stable_cookies() {
+        let support = probe_cookies(stand_in(stable), "example.test.");
+
+        assert!(support.server_cookie);
+        assert!(support.stable);
+        assert!(support.badcookie_handled);
+    }
+
+    #[test]
+    fn ignores_cookie_option() {
+        let support = probe_cookies(stand_in(|_request, _query| (ResponseCode::NoError, Vec::new())), "example.test.");
+
+        assert!(!support.server_cookie && !support.stable && !support.badcookie_handled);
+    }
+
+    #[test]
+    fn rejects_foreign_client_cookie() {
+        let support = probe_cookies(
+            stand_in(|_request, _query| (ResponseCode::NoError, [[0u8; 8], SERVER_COOKIE].concat())),
+            "example.test."
+        );
+
+        assert!(!support.server_cookie);
+    }
+
+    #[test]
+    fn notices_changing_cookie() {
+        let support = probe_cookies(
+            stand_in(|request, query| (ResponseCode::NoError, with_server_cookie(request, &[query as u8; 8]))),
+            "example.test."
+        );
+
+        assert!(support.server_cookie);
+        assert!(!support.stable);
+    }
+
+    #[test]
+    fn accepts_changing_interoperable_cookie() {
+        // RFC 9018: version 1, three reserved bytes, a timestamp and an 8 byte hash
+        let support = probe_cookies(
+            stand_in(|request, query| {
+                let mut server_cookie: Vec<u8> = vec![1, 0, 0, 0];
+                server_cookie.extend_from_slice(&(0x6700_0000 + query).to_be_bytes());
+                server_cookie.extend_from_slice(&[query as u8; 8]);
+
+                (ResponseCode::NoError, with_server_cookie(request, &server_cookie))
+            }),
+            "example.test."
+        );
+
+        assert!(support.server_cookie);
+        assert!(support.stable);
+    }
+
+    #[test]
+    fn bad_cookie_must_be_replaced() {
+        // Echoing back whatever server cookie was sent is not handling it
+        let support = probe_cookies(
+            stand_in(|request, _query| {
+                let option: Vec<u8> = if request.len() > 8 {
+                    request.to_vec()
+                } else {
+                    with_server_cookie(request, &SERVER_COOKIE)
+                };
+
+                (ResponseCode::NoError, option)
+            }),
+            "example.test."
+        );
+
+        assert!(support.server_cookie && support.stable);
+        assert!(!support.badcookie_handled);
+    }
+
+    #[test]
+    fn bad_cookie_refused_without_cookie() {
+        let support = probe_cookies(
+            stand_in(|request, query| {
+                match query {
+                    2 => (ResponseCode::BADCOOKIE, Vec::new()),
+                    _ => (ResponseCode::NoError, with_server_cookie(request, &SERVER_COOKIE)),
+                }
+            }),
+            "example.test."
+        );
+
+        assert!(support.stable);
+        assert!(!support.badcookie_handled);
+    }
+}
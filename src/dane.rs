@@ -0,0 +1,227 @@
+use crate::types::{ Dane, TlsaRecord };
+use crate::tls::hex;
+
+use std::prelude::v1::Result as stdResult;
+
+use anyhow::{ Result, Error };
+use hickory_resolver::Resolver;
+use hickory_resolver::lookup::Lookup;
+use hickory_resolver::error::{ ResolveError, ResolveErrorKind };
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+use hickory_resolver::proto::rr::{ RData, RecordType };
+use hickory_resolver::proto::rr::rdata::TLSA;
+use openssl::hash::{ hash, MessageDigest };
+use openssl::x509::X509;
+use trust_dns_resolver::lookup::Lookup as TDRLookup;
+use trust_dns_resolver::error::ResolveError as TDRResolveError;
+use trust_dns_resolver::proto::rr::RecordType as TDRRecordType;
+use trust_dns_resolver::Resolver as TDRResolver;
+use trust_dns_resolver::config::{
+    ResolverConfig as TDRResolverConfig,
+    ResolverOpts as TDRResolverOpts,
+};
+
+fn tlsa_secure(name: &str) -> bool {
+    let mut opts: TDRResolverOpts = TDRResolverOpts::default();
+    opts.validate = true;
+
+    let resolver: TDRResolver = match TDRResolver::new(TDRResolverConfig::quad9(), opts) {
+        Ok(resolver) => resolver,
+        Err(_e) => {
+            return false;
+        }
+    };
+
+    let result: stdResult<TDRLookup, TDRResolveError> = resolver.lookup(name, TDRRecordType::TLSA);
+
+    result.is_ok()
+}
+
+#[doc = "Certificate data selected by a TLSA record, hashed with its matching type"]
+fn association(cert: &X509, selector: u8, matching_type: u8) -> Option<Vec<u8>> {
+    let selected: Vec<u8> = match selector {
+        0 => cert.to_der().ok()?,
+        1 => cert.public_key().ok()?.public_key_to_der().ok()?,
+        _ => {
+            return None;
+        }
+    };
+
+    match matching_type {
+        0 => Some(selected),
+        1 => hash(MessageDigest::sha256(), &selected).ok().map(|digest| digest.to_vec()),
+        2 => hash(MessageDigest::sha512(), &selected).ok().map(|digest| digest.to_vec()),
+        _ => None,
+    }
+}
+
+#[doc = "RFC 7672: only DANE-TA(2) and DANE-EE(3) are usable for SMTP; EE matches the leaf, TA any issuer in the chain. Without a chain matches stays unset"]
+fn tlsa_record(tlsa: &TLSA, chain: &[X509]) -> TlsaRecord {
+    let usage: u8 = tlsa.cert_usage().into();
+    let selector: u8 = tlsa.selector().into();
+    let matching_type: u8 = tlsa.matching().into();
+
+    let candidates: &[X509] = match usage {
+        3 => chain.get(..1).unwrap_or(&[]),
+        2 => chain.get(1..).unwrap_or(&[]),
+        _ => &[],
+    };
+
+    TlsaRecord {
+        usage,
+        selector,
+        matching_type,
+        data: hex(tlsa.cert_data()),
+        usable: usage == 2 || usage == 3,
+        matches: (!chain.is_empty()).then(|| {
+            candidates.iter().any(|cert: &X509| {
+                association(cert, selector, matching_type).as_deref() == Some(tlsa.cert_data())
+            })
+        }),
+    }
+}
+
+#[doc = "Status of secure TLSA records can only be decided against a chain from a STARTTLS handshake; without one it is unverified"]
+fn assess(dane: &mut Dane) {
+    let usable: usize = dane.records
+        .iter()
+        .filter(|record: &&TlsaRecord| record.usable)
+        .count();
+    let matched: usize = dane.records
+        .iter()
+        .filter(|record: &&TlsaRecord| record.usable && record.matches == Some(true))
+        .count();
+    let verified: bool = dane.records.iter().any(|record: &TlsaRecord| record.matches.is_some());
+
+    // A published next key or issuer alongside the one in use
+    dane.rollover = matched > 0 && usable > 1;
+
+    dane.status = (
+        if !dane.dnssec {
+            "insecure"
+        } else if !verified {
+            "unverified"
+        } else if matched > 0 {
+            "valid"
+        } else {
+            "mismatched"
+        }
+    ).to_string();
+}
+
+#[doc = "TLSA records at _25._tcp of an MX host matched against the chain it presented over STARTTLS; lookup failures give status error"]
+pub fn check_dane(mx_name: &str, chain: &[X509]) -> Dane {
+    let name: String = format!("_25._tcp.{}", mx_name);
+
+    let mut dane: Dane = Dane {
+        name: name.clone(),
+        status: "absent".to_string(),
+        dnssec: false,
+        records: Vec::new(),
+        rollover: false,
+        error: String::new(),
+    };
+
+    if let Err(e) = lookup_dane(&name, chain, &mut dane) {
+        dane.status = "error".to_string();
+        dane.error = e.to_string();
+    }
+
+    dane
+}
+
+fn lookup_dane(name: &str, chain: &[X509], dane: &mut Dane) -> Result<()> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let result: stdResult<Lookup, ResolveError> = resolver.lookup(name, RecordType::TLSA);
+
+    match result {
+        Ok(lookup) => {
+            for record in lookup.record_iter() {
+                if let Some(RData::TLSA(tlsa)) = record.data() {
+                    dane.records.push(tlsa_record(tlsa, chain));
+                }
+            }
+        }
+        // SERVFAIL, timeouts and bogus DNSSEC are not the same as no TLSA records
+        Err(e) =>
+            match e.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => {}
+                _ => {
+                    return Err(Error::new(e));
+                }
+            }
+    }
+
+    if dane.records.is_empty() {
+        return Ok(());
+    }
+
+    dane.dnssec = tlsa_secure(name);
+    assess(dane);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ assess, association, tlsa_record };
+    use crate::test_support::self_signed;
+    use crate::types::{ Dane, TlsaRecord };
+
+    use hickory_resolver::proto::rr::rdata::TLSA;
+    use hickory_resolver::proto::rr::rdata::tlsa::{ CertUsage, Matching, Selector };
+    use openssl::x509::X509;
+
+    fn dane_ee(chain: &[X509], data: Vec<u8>) -> TlsaRecord {
+        let tlsa: TLSA = TLSA::new(CertUsage::from(3), Selector::from(1), Matching::from(1), data);
+        tlsa_record(&tlsa, chain)
+    }
+
+    fn secure(records: Vec<TlsaRecord>) -> Dane {
+        let mut dane: Dane = Dane {
+            name: "_25._tcp.mx.example.test".to_string(),
+            status: "absent".to_string(),
+            dnssec: true,
+            records,
+            rollover: false,
+            error: String::new(),
+        };
+        assess(&mut dane);
+        dane
+    }
+
+    #[test]
+    fn empty_chain_is_unverified() {
+        let (cert, _key) = self_signed("mx.example.test");
+        let data: Vec<u8> = association(&cert, 1, 1).unwrap();
+
+        let record: TlsaRecord = dane_ee(&[], data);
+        assert!(record.usable);
+        assert_eq!(record.matches, None);
+
+        let dane: Dane = secure(vec![record]);
+        assert_eq!(dane.status, "unverified");
+        assert!(!dane.rollover);
+    }
+
+    #[test]
+    fn matches_presented_chain() {
+        let (cert, _key) = self_signed("mx.example.test");
+        let (next, _key) = self_signed("mx.example.test");
+        let chain: Vec<X509> = vec![cert.clone()];
+
+        let dane: Dane = secure(
+            vec![
+                dane_ee(&chain, association(&cert, 1, 1).unwrap()),
+                dane_ee(&chain, association(&next, 1, 1).unwrap())
+            ]
+        );
+        assert_eq!(dane.records[0].matches, Some(true));
+        assert_eq!(dane.records[1].matches, Some(false));
+        assert_eq!(dane.status, "valid");
+        assert!(dane.rollover);
+
+        let dane: Dane = secure(vec![dane_ee(&chain, association(&next, 1, 1).unwrap())]);
+        assert_eq!(dane.status, "mismatched");
+    }
+}
@@ -0,0 +1,292 @@
+use crate::types::{ Dkim, DkimKey };
+use crate::query::txt_records;
+
+use std::env::var;
+
+use anyhow::Result;
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+use openssl::base64::decode_block;
+use openssl::pkey::{ Id, PKey, Public };
+use openssl::rsa::Rsa;
+
+#[doc = "Selectors of common mail providers and signing software, replaced by DKIM_SELECTORS (comma separated) when set"]
+const COMMON_SELECTORS: [&str; 24] = [
+    "default",
+    "dkim",
+    "mail",
+    "email",
+    "smtp",
+    "google",
+    "selector1",
+    "selector2",
+    "k1",
+    "k2",
+    "k3",
+    "s1",
+    "s2",
+    "sig1",
+    "dkim1",
+    "mx",
+    "fm1",
+    "fm2",
+    "fm3",
+    "protonmail",
+    "protonmail2",
+    "protonmail3",
+    "zoho",
+    "mandrill",
+];
+
+pub fn selectors(extra: &[String]) -> Vec<String> {
+    let common: Vec<String> = match var("DKIM_SELECTORS").ok().filter(|value: &String| !value.trim().is_empty()) {
+        Some(value) =>
+            value
+                .split(',')
+                .map(|selector: &str| selector.trim().to_string())
+                .collect(),
+        None =>
+            COMMON_SELECTORS.iter()
+                .map(|selector: &&str| selector.to_string())
+                .collect(),
+    };
+
+    let mut selectors: Vec<String> = Vec::new();
+
+    for selector in extra.iter().chain(common.iter()) {
+        let selector: String = selector.trim().trim_end_matches('.').to_lowercase();
+
+        if !selector.is_empty() && !selectors.contains(&selector) {
+            selectors.push(selector);
+        }
+    }
+
+    selectors
+}
+
+#[doc = "RSA keys are SubjectPublicKeyInfo, some publishers use bare PKCS#1; Ed25519 keys are the raw 32 bytes (RFC 8463)"]
+fn public_key(key_type: &str, der: &[u8]) -> Result<PKey<Public>> {
+    match key_type {
+        "ed25519" => Ok(PKey::public_key_from_raw_bytes(der, Id::ED25519)?),
+        _ =>
+            PKey::public_key_from_der(der).or_else(|_e| {
+                Ok(PKey::from_rsa(Rsa::public_key_from_der_pkcs1(der)?)?)
+            }),
+    }
+}
+
+#[doc = "Parse a DKIM key record (RFC 6376 section 3.6.1) and decode its public key"]
+pub fn parse_key(selector: &str, name: &str, record: &str) -> DkimKey {
+    let mut key: DkimKey = DkimKey {
+        selector: selector.to_string(),
+        name: name.to_string(),
+        record: record.to_string(),
+        key_type: "rsa".to_string(),
+        hash_algorithms: Vec::new(),
+        service_types: Vec::new(),
+        flags: Vec::new(),
+        algorithm: String::new(),
+        key_bits: 0,
+        revoked: false,
+        testing: false,
+        weak: false,
+        valid: false,
+        errors: Vec::new(),
+    };
+
+    let mut public: Option<String> = None;
+
+    for (index, field) in record
+        .split(';')
+        .map(|field: &str| field.trim())
+        .filter(|field: &&str| !field.is_empty())
+        .enumerate() {
+        let (tag, value) = match field.split_once('=') {
+            Some((tag, value)) => (tag.trim(), value.trim()),
+            None => {
+                key.errors.push(format!("Malformed tag {}", field));
+                continue;
+            }
+        };
+
+        let list = |value: &str| -> Vec<String> {
+            value
+                .split(':')
+                .map(|item: &str| item.trim().to_lowercase())
+                .filter(|item: &String| !item.is_empty())
+                .collect()
+        };
+
+        match tag {
+            "v" if index != 0 || value != "DKIM1" => {
+                key.errors.push("v must be DKIM1 and the first tag".to_string());
+            }
+            "k" => {
+                key.key_type = value.to_lowercase();
+            }
+            "h" => {
+                key.hash_algorithms = list(value);
+            }
+            "s" => {
+                key.service_types = list(value);
+            }
+            "t" => {
+                key.flags = list(value);
+            }
+            "p" => {
+                // Whitespace may fold the base64 over several strings
+                public = Some(value.chars().filter(|c: &char| !c.is_whitespace()).collect());
+            }
+            // Unknown tags, including n= notes, are ignored
+            _ => {}
+        }
+    }
+
+    key.testing = key.flags.iter().any(|flag: &String| flag == "y");
+
+    if !["rsa", "ed25519"].contains(&key.key_type.as_str()) {
+        key.errors.push(format!("Unknown key type {}", key.key_type));
+    }
+    if !key.hash_algorithms.is_empty() && !key.hash_algorithms.iter().any(|hash: &String| hash == "sha256") {
+        key.errors.push("h does not allow sha256".to_string());
+    }
+    if !key.service_types.is_empty() && !key.service_types.iter().any(|service: &String| service == "*" || service == "email") {
+        key.errors.push("s does not allow email".to_string());
+    }
+
+    match public.as_deref() {
+        None => {
+            key.errors.push("p tag is missing".to_string());
+        }
+        Some("") => {
+            // An empty p= is an explicit revocation
+            key.revoked = true;
+        }
+        Some(encoded) => {
+            match decode_block(encoded).map_err(anyhow::Error::new).and_then(|der: Vec<u8>| public_key(&key.key_type, &der)) {
+                Ok(public) => {
+                    key.algorithm = (
+                        match public.id() {
+                            Id::RSA => "RSA",
+                            Id::ED25519 => "Ed25519",
+                            _ => "unknown",
+                        }
+                    ).to_string();
+                    key.key_bits = public.bits();
+
+                    if public.id() == Id::RSA && key.key_bits < 1024 {
+                        key.errors.push(format!("RSA key of {} bits is below the 1024 bit minimum", key.key_bits));
+                    }
+                    // RFC 8301: verifiers may accept 1024 bits, signers should use 2048
+                    key.weak = public.id() == Id::RSA && key.key_bits < 2048;
+                }
+                Err(e) => {
+                    key.errors.push(format!("Undecodable public key: {}", e));
+                }
+            }
+        }
+    }
+
+    key.valid = key.errors.is_empty() && !key.revoked;
+
+    key
+}
+
+#[doc = "Probe <selector>._domainkey for the common and requested selectors; DKIM has no way to list the ones in use"]
+pub fn check_dkim(domain: &str, extra_selectors: &[String]) -> Dkim {
+    let domain: &str = domain.trim_end_matches('.');
+
+    let mut dkim: Dkim = Dkim {
+        selectors_checked: selectors(extra_selectors),
+        keys: Vec::new(),
+        valid: false,
+        errors: Vec::new(),
+        error: String::new(),
+    };
+
+    let resolver: Resolver = match Resolver::new(ResolverConfig::quad9(), ResolverOpts::default()) {
+        Ok(resolver) => resolver,
+        Err(e) => {
+            dkim.error = e.to_string();
+            return dkim;
+        }
+    };
+
+    for selector in &dkim.selectors_checked {
+        let name: String = format!("{}._domainkey.{}", selector, domain);
+
+        // One failing selector lookup must not hide the keys found under the others
+        let records: Vec<String> = match txt_records(&resolver, &name) {
+            Ok(records) => records,
+            Err(e) => {
+                dkim.errors.push(format!("{}: {}", selector, e));
+                continue;
+            }
+        };
+
+        // A wildcard or stray TXT without v= or p= is not a key record
+        for record in records
+            .into_iter()
+            .filter(|record: &String| record.starts_with("v=DKIM1") || record.contains("p=")) {
+            dkim.keys.push(parse_key(selector, &name, &record));
+        }
+    }
+
+    dkim.valid = dkim.keys.iter().any(|key: &DkimKey| key.valid);
+
+    dkim
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_key;
+
+    use openssl::base64::encode_block;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+
+    fn rsa_record(bits: u32, tags: &str) -> String {
+        let key = PKey::from_rsa(Rsa::generate(bits).unwrap()).unwrap();
+        format!("v=DKIM1; {}p={}", tags, encode_block(&key.public_key_to_der().unwrap()))
+    }
+
+    #[test]
+    fn decodes_rsa_keys() {
+        let key = parse_key("s1", "s1._domainkey.example.com", &rsa_record(2048, "k=rsa; h=sha256; "));
+        assert!(key.valid, "{:?}", key.errors);
+        assert_eq!(key.algorithm, "RSA");
+        assert_eq!(key.key_bits, 2048);
+        assert!(!key.weak);
+
+        let key = parse_key("s1", "s1._domainkey.example.com", &rsa_record(1024, "t=y; "));
+        assert!(key.valid);
+        assert!(key.weak);
+        assert!(key.testing);
+
+        let key = parse_key("s1", "s1._domainkey.example.com", &rsa_record(512, ""));
+        assert!(!key.valid);
+    }
+
+    #[test]
+    fn decodes_ed25519_keys() {
+        let key = parse_key(
+            "ed",
+            "ed._domainkey.example.com",
+            "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo="
+        );
+
+        assert!(key.valid, "{:?}", key.errors);
+        assert_eq!(key.algorithm, "Ed25519");
+        assert_eq!(key.key_bits, 256);
+    }
+
+    #[test]
+    fn flags_revoked_and_broken_records() {
+        let key = parse_key("old", "old._domainkey.example.com", "v=DKIM1; k=rsa; p=");
+        assert!(key.revoked);
+        assert!(!key.valid);
+
+        let key = parse_key("bad", "bad._domainkey.example.com", "k=dsa; h=sha1; p=bm90IGEga2V5");
+        assert_eq!(key.errors.len(), 3, "{:?}", key.errors);
+    }
+}
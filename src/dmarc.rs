@@ -0,0 +1,371 @@
+use crate::types::{ Dmarc, ExternalReport, ReportUri };
+use crate::psl::organizational_domain;
+use crate::query::txt_records;
+use crate::tlsrpt::report_uri;
+
+use anyhow::Result;
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+
+const POLICIES: [&str; 3] = ["none", "quarantine", "reject"];
+
+fn is_dmarc(record: &str) -> bool {
+    record.split(';').next().map(|version: &str| version.trim() == "v=DMARC1").unwrap_or(false)
+}
+
+#[doc = "Report URIs of a rua or ruf tag; a trailing !size limit is checked and stripped before the URI itself"]
+fn report_uris(value: &str, errors: &mut Vec<String>) -> Vec<ReportUri> {
+    value
+        .split(',')
+        .map(|uri: &str| uri.trim())
+        .filter(|uri: &&str| !uri.is_empty())
+        .map(|uri: &str| {
+            let (address, limit) = match uri.rsplit_once('!') {
+                Some((address, limit)) => (address, Some(limit)),
+                None => (uri, None),
+            };
+
+            if let Some(limit) = limit {
+                let digits: &str = limit.trim_end_matches(['k', 'm', 'g', 't']);
+                let valid: bool =
+                    !digits.is_empty() &&
+                    digits.chars().all(|c: char| c.is_ascii_digit()) &&
+                    limit.len() - digits.len() <= 1;
+
+                if !valid {
+                    errors.push(format!("Invalid size limit in {}", uri));
+                }
+            }
+
+            report_uri(address)
+        })
+        .collect()
+}
+
+fn policy(value: &str, tag: &str, errors: &mut Vec<String>) -> String {
+    let value: String = value.to_lowercase();
+
+    if !POLICIES.contains(&value.as_str()) {
+        errors.push(format!("{} must be none, quarantine or reject", tag));
+    }
+
+    value
+}
+
+fn alignment(value: &str, tag: &str, errors: &mut Vec<String>) -> String {
+    let value: String = value.to_lowercase();
+
+    if value != "r" && value != "s" {
+        errors.push(format!("{} must be r or s", tag));
+    }
+
+    value
+}
+
+#[doc = "Parse _dmarc TXT records per RFC 7489 section 6.3 (np from RFC 9091); more than one v=DMARC1 record means no policy"]
+pub fn parse_dmarc(records: Vec<String>) -> Dmarc {
+    let records: Vec<String> = records
+        .into_iter()
+        .filter(|record: &String| record.starts_with("v=DMARC1"))
+        .collect();
+
+    let mut dmarc: Dmarc = Dmarc {
+        name: String::new(),
+        organizational_domain: String::new(),
+        inherited: false,
+        multiple_records: records.len() > 1,
+        records,
+        valid: false,
+        policy: String::new(),
+        subdomain_policy: String::new(),
+        non_existent_policy: String::new(),
+        effective_policy: String::new(),
+        pct: 100,
+        adkim: "r".to_string(),
+        aspf: "r".to_string(),
+        rua: Vec::new(),
+        ruf: Vec::new(),
+        fo: vec!["0".to_string()],
+        ri: 86400,
+        external_reports: Vec::new(),
+        enforced: false,
+        errors: Vec::new(),
+        error: String::new(),
+    };
+
+    if dmarc.multiple_records {
+        dmarc.errors.push("Multiple v=DMARC1 records".to_string());
+    }
+
+    let record: String = match dmarc.records.first() {
+        Some(record) => record.clone(),
+        None => {
+            return dmarc;
+        }
+    };
+
+    if !is_dmarc(&record) {
+        dmarc.errors.push("Record must start with v=DMARC1".to_string());
+    }
+
+    let mut seen: Vec<String> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+
+    for field in record
+        .split(';')
+        .skip(1)
+        .map(|field: &str| field.trim())
+        .filter(|field: &&str| !field.is_empty()) {
+        let (tag, value) = match field.split_once('=') {
+            Some((tag, value)) => (tag.trim().to_lowercase(), value.trim()),
+            None => {
+                errors.push(format!("Malformed tag {}", field));
+                continue;
+            }
+        };
+
+        if seen.contains(&tag) {
+            errors.push(format!("Duplicate {} tag", tag));
+            continue;
+        }
+        seen.push(tag.clone());
+
+        match tag.as_str() {
+            "p" => {
+                dmarc.policy = policy(value, "p", &mut errors);
+            }
+            "sp" => {
+                dmarc.subdomain_policy = policy(value, "sp", &mut errors);
+            }
+            "np" => {
+                dmarc.non_existent_policy = policy(value, "np", &mut errors);
+            }
+            "pct" => {
+                match value.parse::<u32>() {
+                    Ok(pct) if pct <= 100 => {
+                        dmarc.pct = pct;
+                    }
+                    _ => {
+                        errors.push(format!("pct must be 0 to 100, not {}", value));
+                    }
+                }
+            }
+            "adkim" => {
+                dmarc.adkim = alignment(value, "adkim", &mut errors);
+            }
+            "aspf" => {
+                dmarc.aspf = alignment(value, "aspf", &mut errors);
+            }
+            "rua" => {
+                dmarc.rua = report_uris(value, &mut errors);
+            }
+            "ruf" => {
+                dmarc.ruf = report_uris(value, &mut errors);
+            }
+            "fo" => {
+                dmarc.fo = value
+                    .split(':')
+                    .map(|option: &str| option.trim().to_lowercase())
+                    .collect();
+
+                if dmarc.fo.iter().any(|option: &String| !["0", "1", "d", "s"].contains(&option.as_str())) {
+                    errors.push(format!("fo must be a colon separated list of 0, 1, d and s, not {}", value));
+                }
+            }
+            "ri" => {
+                match value.parse::<u32>() {
+                    Ok(ri) => {
+                        dmarc.ri = ri;
+                    }
+                    Err(_e) => {
+                        errors.push(format!("Invalid ri {}", value));
+                    }
+                }
+            }
+            "rf" if value.split(':').any(|format: &str| !format.trim().eq_ignore_ascii_case("afrf")) => {
+                errors.push(format!("Unsupported rf {}", value));
+            }
+            // Unknown tags are ignored
+            _ => {}
+        }
+    }
+
+    if dmarc.policy.is_empty() {
+        errors.push("p tag is missing".to_string());
+    }
+
+    dmarc.errors.extend(errors);
+    dmarc.effective_policy = dmarc.policy.clone();
+
+    dmarc.valid =
+        dmarc.errors.is_empty() &&
+        dmarc.rua
+            .iter()
+            .chain(dmarc.ruf.iter())
+            .all(|uri: &ReportUri| uri.valid);
+
+    dmarc
+}
+
+#[doc = "Domain of a mailto report address, None for other schemes and invalid URIs"]
+fn destination(uri: &ReportUri) -> Option<String> {
+    if !uri.valid || uri.scheme != "mailto" {
+        return None;
+    }
+
+    uri.uri
+        .split('?')
+        .next()
+        .and_then(|address: &str| address.rsplit_once('@'))
+        .map(|(_local, domain)| domain.trim_end_matches('.').to_lowercase())
+}
+
+#[doc = "RFC 7489 section 7.1: a destination outside the organizational domain must publish <domain>._report._dmarc.<destination>"]
+fn external_reports(resolver: &Resolver, domain: &str, uris: &[ReportUri]) -> Vec<ExternalReport> {
+    let organizational: String = organizational_domain(domain);
+    let mut reports: Vec<ExternalReport> = Vec::new();
+
+    for uri in uris {
+        let destination: String = match destination(uri) {
+            Some(destination) if organizational_domain(&destination) != organizational => destination,
+            _ => {
+                continue;
+            }
+        };
+
+        let name: String = format!("{}._report._dmarc.{}", domain, destination);
+        // A failing lookup at a third party only leaves that destination unauthorized
+        let (authorized, error): (bool, String) = match txt_records(resolver, &name) {
+            Ok(records) => (records.iter().any(|record: &String| is_dmarc(record)), String::new()),
+            Err(e) => (false, e.to_string()),
+        };
+
+        reports.push(ExternalReport {
+            uri: uri.uri.clone(),
+            destination,
+            name,
+            authorized,
+            error,
+        });
+    }
+
+    reports
+}
+
+#[doc = "DMARC policy of a domain, from its own _dmarc record or else the one of its organizational domain; a failed lookup is reported in error"]
+pub fn check_dmarc(domain: &str) -> Dmarc {
+    let domain: String = domain.trim_end_matches('.').to_lowercase();
+
+    match lookup_dmarc(&domain) {
+        Ok(dmarc) => dmarc,
+        Err(e) => {
+            let mut dmarc: Dmarc = parse_dmarc(Vec::new());
+            dmarc.name = format!("_dmarc.{}", domain);
+            dmarc.organizational_domain = organizational_domain(&domain);
+            dmarc.error = e.to_string();
+            dmarc
+        }
+    }
+}
+
+fn lookup_dmarc(domain: &str) -> Result<Dmarc> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let organizational: String = organizational_domain(domain);
+
+    let mut name: String = format!("_dmarc.{}", domain);
+    let mut records: Vec<String> = txt_records(&resolver, &name)?;
+
+    let inherited: bool =
+        organizational != *domain && !records.iter().any(|record: &String| record.starts_with("v=DMARC1"));
+
+    if inherited {
+        name = format!("_dmarc.{}", organizational);
+        records = txt_records(&resolver, &name)?;
+    }
+
+    let mut dmarc: Dmarc = parse_dmarc(records);
+
+    dmarc.name = name;
+    dmarc.organizational_domain = organizational;
+    dmarc.inherited = inherited && !dmarc.records.is_empty();
+
+    // Subdomains get sp, falling back to p, from the organizational domain record
+    if dmarc.inherited && !dmarc.subdomain_policy.is_empty() {
+        dmarc.effective_policy = dmarc.subdomain_policy.clone();
+    }
+
+    // Reports go to the domain that published the record
+    let policy_domain: &str = dmarc.name.trim_start_matches("_dmarc.");
+    let mut external: Vec<ExternalReport> = external_reports(&resolver, policy_domain, &dmarc.rua);
+    external.extend(external_reports(&resolver, policy_domain, &dmarc.ruf));
+    dmarc.external_reports = external;
+
+    dmarc.enforced =
+        dmarc.valid &&
+        dmarc.pct == 100 &&
+        (dmarc.effective_policy == "quarantine" || dmarc.effective_policy == "reject");
+
+    Ok(dmarc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ destination, parse_dmarc };
+
+    #[test]
+    fn parses_all_tags() {
+        let dmarc = parse_dmarc(
+            vec![
+                "v=DMARC1; p=reject; sp=quarantine; np=reject; pct=100; adkim=s; aspf=r; rua=mailto:dmarc@example.com!10m; ruf=mailto:forensic@example.com; fo=1:d; ri=3600".to_string()
+            ]
+        );
+
+        assert!(dmarc.valid, "{:?}", dmarc.errors);
+        assert_eq!(dmarc.policy, "reject");
+        assert_eq!(dmarc.subdomain_policy, "quarantine");
+        assert_eq!(dmarc.non_existent_policy, "reject");
+        assert_eq!(dmarc.adkim, "s");
+        assert_eq!(dmarc.rua[0].uri, "mailto:dmarc@example.com");
+        assert_eq!(dmarc.fo, vec!["1", "d"]);
+        assert_eq!(dmarc.ri, 3600);
+    }
+
+    #[test]
+    fn flags_syntax_errors() {
+        let dmarc = parse_dmarc(
+            vec!["v=DMARC1; p=block; pct=150; aspf=x; fo=2; adkim=r; adkim=s; rua=mailto:a@example.com!10x".to_string()]
+        );
+
+        assert!(!dmarc.valid);
+        assert_eq!(dmarc.errors.len(), 6, "{:?}", dmarc.errors);
+    }
+
+    #[test]
+    fn flags_multiple_and_missing_records() {
+        let dmarc = parse_dmarc(
+            vec!["v=DMARC1; p=none".to_string(), "v=DMARC1; p=reject".to_string(), "v=spf1 -all".to_string()]
+        );
+
+        assert!(dmarc.multiple_records);
+        assert!(!dmarc.valid);
+
+        let dmarc = parse_dmarc(vec!["v=DMARC1; rua=mailto:dmarc@example.com".to_string()]);
+        assert!(!dmarc.valid);
+        assert_eq!(dmarc.errors, vec!["p tag is missing"]);
+
+        assert!(parse_dmarc(Vec::new()).records.is_empty());
+    }
+
+    #[test]
+    fn finds_report_destinations() {
+        let dmarc = parse_dmarc(
+            vec![
+                "v=DMARC1; p=none; rua=mailto:dmarc@Reports.Example.NET!10m,https://example.org/r,mailto:bad".to_string()
+            ]
+        );
+
+        assert_eq!(destination(&dmarc.rua[0]), Some("reports.example.net".to_string()));
+        assert_eq!(destination(&dmarc.rua[1]), None);
+        assert_eq!(destination(&dmarc.rua[2]), None);
+    }
+}
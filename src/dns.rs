@@ -9,10 +9,14 @@ use crate::types::{
     SOARecord,
     DNSSEC,
 };
+use crate::cookies::{ check_cookies, CookieSupport };
+use crate::reverse::ptr_record;
+use crate::transport::check_encrypted_transports;
 
 use std::error::Error as stdError;
 use std::prelude::v1::Result as stdResult;
 use std::net::{ IpAddr, SocketAddr, TcpStream };
+use std::thread;
 use anyhow::{ Result, Error };
 use hickory_resolver::Resolver;
 use hickory_resolver::lookup::Lookup;
@@ -184,6 +188,22 @@ pub fn check_caa(domain: &str) -> Result<CheckCAA, Error> {
     }
 }
 
+#[doc = "Cookie and DoT/DoH probes wait on timeouts, so every nameserver address is probed at once"]
+fn probe_nameservers(nsaddresses: &mut [NSAddresses], probes: &[(IpAddr, String)], domain: &str) {
+    thread::scope(|scope| {
+        for (nsaddress, (ip, nsdomain)) in nsaddresses.iter_mut().zip(probes) {
+            scope.spawn(move || {
+                let cookies: CookieSupport = check_cookies(*ip, domain);
+
+                nsaddress.cookies = cookies.server_cookie;
+                nsaddress.cookies_stable = cookies.stable;
+                nsaddress.badcookie_handled = cookies.badcookie_handled;
+                nsaddress.encrypted_transports = check_encrypted_transports(*ip, nsdomain, domain);
+            });
+        }
+    });
+}
+
 pub fn check_ns(domain: &str) -> Result<NSRecord, Error> {
     let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
     let result: stdResult<Lookup, ResolveError> = resolver.lookup(domain, RecordType::NS);
@@ -207,6 +227,8 @@ pub fn check_ns(domain: &str) -> Result<NSRecord, Error> {
     };
 
     let mut soa_domain: String = "".to_string();
+    // Address and host of every entry in nsaddresses, for the cookie and DoT/DoH probes
+    let mut probes: Vec<(IpAddr, String)> = Vec::new();
 
     match soa {
         Ok(lookup) => {
@@ -280,12 +302,17 @@ pub fn check_ns(domain: &str) -> Result<NSRecord, Error> {
 
                             let ipv4address: String = address.to_string();
 
+                            let my_ip: IpAddr = match address.parse() {
+                                Ok(ipv4) => IpAddr::V4(ipv4),
+                                Err(_e) => {
+                                    continue;
+                                }
+                            };
+
                             ipv4available = true;
 
                             ipv4_addresses.push(address.to_string());
 
-                            let my_ip: IpAddr = IpAddr::V4(address.parse().unwrap());
-
                             let mut udp: bool = false;
                             let tcp: bool;
                             let mut operational: bool = false;
@@ -345,36 +372,12 @@ pub fn check_ns(domain: &str) -> Result<NSRecord, Error> {
                                 }
                             }
 
-                            let mut in_addr_arpa: String = address
-                                .split('.')
-                                .rev()
-                                .collect::<Vec<&str>>()
-                                .join(".");
-
-                            in_addr_arpa = format!("{}.in-addr.arpa", in_addr_arpa);
-
-                            let mut ptr: String = "".to_string();
-
-                            match
-                                stdResult::<Lookup, ResolveError>::from(
-                                    resolver.lookup(&in_addr_arpa, RecordType::PTR)
-                                )
-                            {
-                                Ok(lookup) => {
-                                    for record in lookup.record_iter() {
-                                        let record_str: String = record.to_string();
-                                        let parts: Vec<&str> = record_str
-                                            .split_whitespace()
-                                            .collect();
-
-                                        ptr = parts.get(4).unwrap_or(&"").to_string();
-                                    }
-                                }
-                                Err(_e) => {}
-                            }
+                            let ptr: String = ptr_record(&resolver, my_ip).unwrap_or_default();
 
                             let mut referral_ns_soa: bool = false;
 
+                            probes.push((my_ip, nsdomain.clone()));
+
                             if ptr == soa_domain {
                                 referral_ns_soa = true;
                             }
@@ -388,6 +391,10 @@ pub fn check_ns(domain: &str) -> Result<NSRecord, Error> {
                                 recursive: recursive,
                                 udp: udp,
                                 tcp: tcp,
+                                cookies: false,
+                                cookies_stable: false,
+                                badcookie_handled: false,
+                                encrypted_transports: Vec::new(),
                             });
                         }
                     }
@@ -402,9 +409,14 @@ pub fn check_ns(domain: &str) -> Result<NSRecord, Error> {
 
                             let address: String = parts.get(4).unwrap_or(&"").to_string();
 
-                            ipv6available = true;
+                            let my_ip: IpAddr = match address.parse() {
+                                Ok(ipv6) => IpAddr::V6(ipv6),
+                                Err(_e) => {
+                                    continue;
+                                }
+                            };
 
-                            let my_ip: IpAddr = IpAddr::V6(address.parse().unwrap());
+                            ipv6available = true;
 
                             let mut udp: bool = false;
                             let tcp: bool;
@@ -469,79 +481,9 @@ pub fn check_ns(domain: &str) -> Result<NSRecord, Error> {
 
                             let ipv6address: String = address.to_string();
 
-                            fn expand_ipv6_address(ipv6: &str) -> Result<String, &'static str> {
-                                let parts: Vec<&str> = ipv6.split("::").collect();
-                                if parts.len() > 2 {
-                                    return Err("Invalid IPv6 address");
-                                }
-
-                                let hextets_before_double_colon: Vec<&str> = parts[0]
-                                    .split(':')
-                                    .collect::<Vec<&str>>();
-                                let hextets_after_double_colon: Vec<&str> = if parts.len() == 2 {
-                                    parts[1].split(':').collect::<Vec<&str>>()
-                                } else {
-                                    vec![]
-                                };
-
-                                let missing_hextets =
-                                    8 -
-                                    hextets_before_double_colon.len() -
-                                    hextets_after_double_colon.len();
-                                let zeros = vec!["0000"; missing_hextets];
-
-                                let combined: Vec<&str> = [
-                                    &hextets_before_double_colon[..],
-                                    &zeros[..],
-                                    &hextets_after_double_colon[..],
-                                ].concat();
-
-                                let expanded: Vec<String> = combined
-                                    .into_iter()
-                                    .map(|part| format!("{:0>4}", part))
-                                    .collect();
-
-                                Ok(expanded.join(":"))
-                            }
-
-                            fn ipv6_to_ptr(ipv6: &str) -> Result<String, &'static str> {
-                                let expanded: String = expand_ipv6_address(ipv6)?;
-
-                                let ptr: String = expanded
-                                    .replace(":", "")
-                                    .chars()
-                                    .rev()
-                                    .enumerate()
-                                    .map(|(i, c)| (
-                                        if i > 0 {
-                                            format!(".{}", c)
-                                        } else {
-                                            c.to_string()
-                                        }
-                                    ))
-                                    .collect::<String>();
-
-                                Ok(format!("{}{}", ptr, ".ip6.arpa"))
-                            }
-
-                            let ipv6_arpa: stdResult<String, &str> = ipv6_to_ptr(&ipv6address);
-
-                            let ptr_lookup: stdResult<Lookup, ResolveError> = resolver.lookup(
-                                &ipv6_arpa.unwrap(),
-                                RecordType::PTR
-                            );
+                            let ptr: String = ptr_record(&resolver, my_ip).unwrap_or_default();
 
-                            let ptr: String = ptr_lookup
-                                .unwrap()
-                                .record_iter()
-                                .next()
-                                .unwrap()
-                                .to_string()
-                                .split_whitespace()
-                                .collect::<Vec<&str>>()
-                                .get(4)
-                                .unwrap_or(&"")
-                                .to_string();
+                            probes.push((my_ip, nsdomain.clone()));
 
                             if ptr == soa_domain {
                                 referral_ns_soa = true;
@@ -556,6 +498,10 @@ pub fn check_ns(domain: &str) -> Result<NSRecord, Error> {
                                 recursive: recursive,
                                 udp: udp,
                                 tcp: tcp,
+                                cookies: false,
+                                cookies_stable: false,
+                                badcookie_handled: false,
+                                encrypted_transports: Vec::new(),
                             });
                         }
                     }
@@ -575,6 +521,8 @@ pub fn check_ns(domain: &str) -> Result<NSRecord, Error> {
                 });
             }
 
+            probe_nameservers(&mut ns_records.nsaddresses, &probes, domain);
+
             Ok(ns_records)
         }
         Err(_e) => Err(Error::msg("No NS records found")),
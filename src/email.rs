@@ -1,13 +1,44 @@
-use crate::types::{ Email, Mx };
+use crate::types::{
+    Bimi,
+    CertificateChain,
+    Dane,
+    Dkim,
+    DkimKey,
+    Dmarc,
+    Email,
+    MtaSts,
+    Mx,
+    NullMx,
+    RelayTest,
+    SmtpProbe,
+    Spf,
+    TlsRpt,
+    TlsScan,
+};
+use crate::reverse::ptr_name;
+use crate::smtp::{ probe_smtp, relay_test, starttls, SmtpConfig };
+use crate::certificate::check_chain;
+use crate::dane::check_dane;
+use crate::mtasts::check_mta_sts;
+use crate::tlsrpt::check_tls_rpt;
+use crate::spf::check_spf;
+use crate::dmarc::check_dmarc;
+use crate::dkim::check_dkim;
+use crate::bimi::check_bimi;
+use crate::ciphers::scan_tls;
+use crate::query::{ addresses, lookup_rdata, name_exists };
 
+use std::env::var;
+use std::net::IpAddr;
 use std::prelude::v1::Result as stdResult;
 
 use anyhow::{ Result, Error };
+use openssl::x509::X509;
 
 use hickory_resolver::Resolver;
 use hickory_resolver::lookup::Lookup;
 use hickory_resolver::error::ResolveError;
-use hickory_resolver::proto::rr::RecordType;
+use hickory_resolver::proto::rr::{ RData, Record, RecordType };
 use hickory_resolver::config::{ NameServerConfig, Protocol, ResolverConfig, ResolverOpts };
 use trust_dns_resolver::lookup::Lookup as TDRLookup;
 use trust_dns_resolver::error::ResolveError as TDRResolveError;
@@ -60,41 +91,270 @@ fn check_dnssec(domain: &str) -> Result<bool> {
     }
 }
 
-pub fn check_email(domain: &str) -> Result<Email> {
+#[doc = "Preference and exchange of every MX record; preferences are the full u16 range"]
+fn exchanges(records: &[RData]) -> Vec<(u16, String)> {
+    records
+        .iter()
+        .filter_map(|rdata: &RData| {
+            match rdata {
+                RData::MX(mx) => Some((mx.preference(), mx.exchange().to_string())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[doc = "RFC 5321 section 5.1 checks of one MX exchange"]
+struct MxTarget {
+    ip_literal: bool,
+    exists: bool,
+    duplicate: bool,
+    errors: Vec<String>,
+}
+
+#[doc = "Validate an exchange against the lookups made for it: its CNAME target, whether the name exists and how many addresses it has"]
+fn validate_exchange(
+    name: &str,
+    exchanges: &[(u16, String)],
+    cname: &str,
+    resolves: bool,
+    address_count: usize
+) -> MxTarget {
+    let mut errors: Vec<String> = Vec::new();
+    let host: &str = name.trim_end_matches('.');
+
+    // RFC 5321 section 5.1: the exchange must be a hostname with address records
+    let ip_literal: bool = host.trim_matches(['[', ']']).parse::<IpAddr>().is_ok();
+    if ip_literal {
+        errors.push(format!("{} is an IP address, not a hostname", host));
+    }
+
+    if !cname.is_empty() {
+        errors.push(format!("{} is a CNAME to {}", host, cname));
+    }
+
+    let exists: bool = ip_literal || resolves;
+    if !exists {
+        errors.push(format!("{} does not exist", host));
+    }
+
+    let duplicate: bool = exchanges
+        .iter()
+        .filter(|(_preference, other)| other.eq_ignore_ascii_case(name))
+        .count() > 1;
+    if duplicate {
+        errors.push(format!("{} is listed more than once", host));
+    }
+
+    if exists && !ip_literal && address_count == 0 {
+        errors.push(format!("{} has no A or AAAA records", host));
+    }
+
+    MxTarget {
+        ip_literal,
+        exists,
+        duplicate,
+        errors,
+    }
+}
+
+#[doc = "Null MX validity and the non-mail posture recommended with it: SPF -all, DMARC reject and no DKIM keys"]
+fn null_mx(preferences: &[u16], other_mx: usize, spf: &Spf, dmarc: &Dmarc, dkim: &Dkim) -> NullMx {
+    let mut null_mx: NullMx = NullMx {
+        present: !preferences.is_empty(),
+        valid: false,
+        spf_fail_all: spf.record.trim().eq_ignore_ascii_case("v=spf1 -all"),
+        dmarc_reject: dmarc.valid && dmarc.effective_policy == "reject",
+        no_dkim: dkim.keys.iter().all(|key: &DkimKey| key.revoked),
+        recommended_posture: false,
+        errors: Vec::new(),
+    };
+
+    if !null_mx.present {
+        return null_mx;
+    }
+
+    if other_mx > 0 || preferences.len() > 1 {
+        null_mx.errors.push("Null MX must be the only MX record".to_string());
+    }
+    if preferences.iter().any(|preference: &u16| *preference != 0) {
+        null_mx.errors.push("Null MX preference should be 0".to_string());
+    }
+
+    null_mx.valid = null_mx.errors.is_empty();
+    null_mx.recommended_posture =
+        null_mx.valid && null_mx.spf_fail_all && null_mx.dmarc_reject && null_mx.no_dkim;
+
+    null_mx
+}
+
+#[doc = "Per request settings of the email check; SMTP probing and the open relay test are off unless SMTP_PROBE=true, SMTP_RELAY_TEST=true or requested"]
+pub struct EmailOptions {
+    pub dkim_selectors: Vec<String>,
+    pub smtp_probe: bool,
+    pub relay_test: bool,
+}
+
+fn env_flag(name: &str) -> bool {
+    var(name)
+        .map(|value: String| value.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+impl Default for EmailOptions {
+    fn default() -> Self {
+        EmailOptions {
+            dkim_selectors: Vec::new(),
+            smtp_probe: env_flag("SMTP_PROBE"),
+            relay_test: env_flag("SMTP_RELAY_TEST"),
+        }
+    }
+}
+
+pub fn check_email(domain: &str, options: &EmailOptions) -> Result<Email> {
     let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
     let result: stdResult<Lookup, ResolveError> = resolver.lookup(domain, RecordType::MX);
 
     let mut mx_records: Vec<Mx> = Vec::new();
+    let mut null_mx_preferences: Vec<u16> = Vec::new();
+    let smtp_config: SmtpConfig = SmtpConfig::default();
 
     match result {
         Ok(lookup) => {
-            for record in lookup.record_iter() {
-                let record_str: String = record.to_string();
-                let parts: Vec<&str> = record_str.split_whitespace().collect();
-                let preference_string = parts.get(4).unwrap_or(&"0");
-                let preference: i8 = preference_string.parse().unwrap_or(0);
-                let name: String = parts.get(5).unwrap_or(&"").to_string();
-                let ipv4 = check_record(&name, RecordType::A).unwrap_or("".to_string());
-                let ipv6 = check_record(&name, RecordType::AAAA).unwrap_or("".to_string());
-
-                let mut in_addr_arpa: String = ipv4
-                    .split('.')
-                    .rev()
-                    .collect::<Vec<&str>>()
-                    .join(".");
-
-                in_addr_arpa = format!("{}.in-addr.arpa", in_addr_arpa);
-
-                let ptr = check_record(&in_addr_arpa, RecordType::PTR).unwrap_or("".to_string());
+            let records: Vec<RData> = lookup
+                .record_iter()
+                .filter_map(|record: &Record| record.data().cloned())
+                .collect();
+            let exchanges: Vec<(u16, String)> = exchanges(&records);
+
+            for (preference, name) in exchanges.iter().cloned() {
+                // RFC 7505 null MX: the root as exchange says the domain accepts no mail
+                if name == "." {
+                    null_mx_preferences.push(preference);
+                    continue;
+                }
+
+                let cname: String = lookup_rdata(&resolver, &name, RecordType::CNAME)
+                    .unwrap_or_default()
+                    .iter()
+                    .find_map(|rdata: &RData| {
+                        match rdata {
+                            RData::CNAME(cname) => Some(cname.0.to_string()),
+                            _ => None,
+                        }
+                    })
+                    .unwrap_or_default();
+                let resolves: bool = name_exists(&resolver, &name).unwrap_or(true);
+                let ipv4_addresses: Vec<IpAddr> = addresses(&resolver, &name, RecordType::A).unwrap_or_default();
+                let ipv6_addresses: Vec<IpAddr> = addresses(&resolver, &name, RecordType::AAAA).unwrap_or_default();
+
+                let target: MxTarget = validate_exchange(
+                    &name,
+                    &exchanges,
+                    &cname,
+                    resolves,
+                    ipv4_addresses.len() + ipv6_addresses.len()
+                );
+
+                let ipv4: String = ipv4_addresses.first().map(|ip: &IpAddr| ip.to_string()).unwrap_or_default();
+                let ipv6: String = ipv6_addresses.first().map(|ip: &IpAddr| ip.to_string()).unwrap_or_default();
+
+                let ptr: String = match ipv4.parse::<IpAddr>() {
+                    Ok(ip) => check_record(&ptr_name(ip), RecordType::PTR).unwrap_or("".to_string()),
+                    Err(_e) => "".to_string(),
+                };
                 let dnssec = check_dnssec(&name).unwrap_or(false);
+                let mx_addresses: Vec<IpAddr> = ipv4_addresses
+                    .iter()
+                    .chain(ipv6_addresses.iter())
+                    .cloned()
+                    .collect();
+
+                // Connecting to every MX is slow and intrusive, so it only runs when asked for
+                let probes: Vec<(SmtpProbe, Vec<X509>)> = if options.smtp_probe {
+                    mx_addresses
+                        .iter()
+                        .map(|ip: &IpAddr| probe_smtp(*ip, &name, &smtp_config))
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+
+                let relay: Vec<RelayTest> = if options.relay_test {
+                    let targets: Vec<IpAddr> = if options.smtp_probe {
+                        probes
+                            .iter()
+                            .filter(|(probe, _chain)| probe.reachable)
+                            .filter_map(|(probe, _chain)| probe.ip.parse::<IpAddr>().ok())
+                            .collect()
+                    } else {
+                        mx_addresses.clone()
+                    };
+
+                    targets
+                        .iter()
+                        .map(|ip: &IpAddr| relay_test(*ip, &name, &smtp_config))
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+
+                // Enumerating ciphers takes dozens of handshakes, so only the first address with working STARTTLS
+                let negotiated: Option<(IpAddr, &[X509])> = probes
+                    .iter()
+                    .filter(|(probe, _chain)| probe.starttls_negotiated)
+                    .find_map(|(probe, chain)| {
+                        probe.ip
+                            .parse::<IpAddr>()
+                            .ok()
+                            .map(|ip: IpAddr| (ip, chain.as_slice()))
+                    });
+
+                let tls: Option<TlsScan> = negotiated.map(|(ip, _chain)| {
+                    scan_tls(&ip.to_string(), &name, &(|| starttls(ip, &smtp_config)))
+                });
+
+                // The chain comes from the probe handshake rather than another connection
+                let certificate: Option<CertificateChain> = negotiated.map(|(ip, chain)| {
+                    let mut result: CertificateChain = check_chain(&ip.to_string(), &name, chain);
+                    if chain.is_empty() {
+                        result.error = "Server sent no certificate".to_string();
+                    }
+                    result
+                });
+
+                let presented: &[X509] = negotiated.map(|(_ip, chain)| chain).unwrap_or(&[]);
+                let dane: Dane = check_dane(&name, presented);
+                let smtp: Vec<SmtpProbe> = probes
+                    .into_iter()
+                    .map(|(probe, _chain)| probe)
+                    .collect();
 
                 let mx_record = Mx {
                     name: name,
                     ipv4: ipv4,
                     ipv6: ipv6,
+                    ipv4_addresses: ipv4_addresses
+                        .iter()
+                        .map(|ip: &IpAddr| ip.to_string())
+                        .collect(),
+                    ipv6_addresses: ipv6_addresses
+                        .iter()
+                        .map(|ip: &IpAddr| ip.to_string())
+                        .collect(),
                     ptr: ptr,
                     preference: preference,
                     dnssec: dnssec,
+                    smtp,
+                    relay,
+                    tls,
+                    certificate,
+                    dane,
+                    cname,
+                    ip_literal: target.ip_literal,
+                    exists: target.exists,
+                    duplicate: target.duplicate,
+                    errors: target.errors,
                 };
 
                 mx_records.push(mx_record);
@@ -106,9 +366,220 @@ pub fn check_email(domain: &str) -> Result<Email> {
         }
     }
 
+    let mx_names: Vec<String> = mx_records
+        .iter()
+        .map(|mx: &Mx| mx.name.clone())
+        .collect();
+    let mta_sts: MtaSts = check_mta_sts(domain, &mx_names);
+    let tls_rpt: TlsRpt = check_tls_rpt(domain);
+    let spf: Spf = check_spf(domain);
+    let dmarc: Dmarc = check_dmarc(domain);
+    let dkim: Dkim = check_dkim(domain, &options.dkim_selectors);
+    let bimi: Bimi = check_bimi(domain, &dmarc);
+
+    let null_mx: NullMx = null_mx(&null_mx_preferences, mx_records.len(), &spf, &dmarc, &dkim);
+
     let email = Email {
+        null_mx,
         mx: mx_records,
+        mta_sts,
+        tls_rpt,
+        spf,
+        dmarc,
+        dkim,
+        bimi,
     };
 
     Ok(email)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ exchanges, null_mx, validate_exchange };
+    use crate::types::Dkim;
+    use crate::spf::spf;
+    use crate::dmarc::parse_dmarc;
+    use crate::dkim::parse_key;
+
+    use hickory_resolver::proto::rr::{ Name, RData };
+    use hickory_resolver::proto::rr::rdata::MX;
+
+    fn mx(preference: u16, exchange: &str) -> RData {
+        RData::MX(MX::new(preference, Name::from_ascii(exchange).unwrap()))
+    }
+
+    #[test]
+    fn keeps_preferences_above_255() {
+        assert_eq!(
+            exchanges(&[mx(200, "mx1.example.test."), mx(65535, "mx2.example.test."), mx(0, ".")]),
+            vec![
+                (200, "mx1.example.test.".to_string()),
+                (65535, "mx2.example.test.".to_string()),
+                (0, ".".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_plain_hostname() {
+        let list = vec![(10, "mx.example.test.".to_string())];
+        let target = validate_exchange("mx.example.test.", &list, "", true, 2);
+
+        assert!(target.exists);
+        assert!(!target.ip_literal && !target.duplicate);
+        assert!(target.errors.is_empty(), "{:?}", target.errors);
+    }
+
+    #[test]
+    fn flags_invalid_targets() {
+        let list = vec![
+            (10, "mail.example.test.".to_string()),
+            (10, "192.0.2.1".to_string()),
+            (20, "gone.example.test.".to_string()),
+            (30, "dup.example.test.".to_string()),
+            (40, "DUP.example.test.".to_string()),
+            (50, "bare.example.test.".to_string())
+        ];
+
+        let target = validate_exchange("mail.example.test.", &list, "mx.provider.test.", true, 1);
+        assert_eq!(target.errors, vec!["mail.example.test is a CNAME to mx.provider.test."]);
+
+        // An IP literal neither has to exist as a name nor have address records
+        let target = validate_exchange("192.0.2.1", &list, "", false, 0);
+        assert!(target.ip_literal && target.exists);
+        assert_eq!(target.errors, vec!["192.0.2.1 is an IP address, not a hostname"]);
+
+        let target = validate_exchange("gone.example.test.", &list, "", false, 0);
+        assert!(!target.exists);
+        assert_eq!(target.errors, vec!["gone.example.test does not exist"]);
+
+        let target = validate_exchange("dup.example.test.", &list, "", true, 1);
+        assert!(target.duplicate);
+        assert_eq!(target.errors, vec!["dup.example.test is listed more than once"]);
+
+        let target = validate_exchange("bare.example.test.", &list, "", true, 0);
+        assert_eq!(target.errors, vec!["bare.example.test has no A or AAAA records"]);
+    }
+
+    fn dkim(records: &[&str]) -> Dkim {
+        Dkim {
+            selectors_checked: vec!["default".to_string()],
+            keys: records
+                .iter()
+                .map(|record: &&str| parse_key("default", "default._domainkey.example.test", record))
+                .collect(),
+            valid: false,
+            errors: Vec::new(),
+            error: String::new(),
+        }
+    }
+
+    const SPF_FAIL_ALL: &str = "v=spf1 -all";
+    const DMARC_REJECT: &str = "v=DMARC1; p=reject";
+
+    #[test]
+    fn accepts_single_null_mx() {
+        let result = null_mx(
+            &[0],
+            0,
+            &spf(vec![SPF_FAIL_ALL.to_string()]),
+            &parse_dmarc(vec![DMARC_REJECT.to_string()]),
+            &dkim(&[])
+        );
+
+        assert!(result.present && result.valid);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert!(result.spf_fail_all && result.dmarc_reject && result.no_dkim);
+        assert!(result.recommended_posture);
+    }
+
+    #[test]
+    fn absent_without_null_mx() {
+        let result = null_mx(&[], 2, &spf(Vec::new()), &parse_dmarc(Vec::new()), &dkim(&[]));
+
+        assert!(!result.present && !result.valid && !result.recommended_posture);
+        assert!(result.errors.is_empty());
+    }
+
+    #[test]
+    fn rejects_null_mx_beside_real_mx() {
+        let result = null_mx(
+            &[0],
+            1,
+            &spf(vec![SPF_FAIL_ALL.to_string()]),
+            &parse_dmarc(vec![DMARC_REJECT.to_string()]),
+            &dkim(&[])
+        );
+
+        assert!(result.present && !result.valid && !result.recommended_posture);
+        assert_eq!(result.errors, vec!["Null MX must be the only MX record"]);
+    }
+
+    #[test]
+    fn rejects_nonzero_preference() {
+        let result = null_mx(
+            &[10],
+            0,
+            &spf(vec![SPF_FAIL_ALL.to_string()]),
+            &parse_dmarc(vec![DMARC_REJECT.to_string()]),
+            &dkim(&[])
+        );
+
+        assert!(!result.valid && !result.recommended_posture);
+        assert_eq!(result.errors, vec!["Null MX preference should be 0"]);
+    }
+
+    #[test]
+    fn rejects_repeated_null_mx() {
+        let result = null_mx(
+            &[0, 0],
+            0,
+            &spf(vec![SPF_FAIL_ALL.to_string()]),
+            &parse_dmarc(vec![DMARC_REJECT.to_string()]),
+            &dkim(&[])
+        );
+
+        assert!(!result.valid && !result.recommended_posture);
+        assert_eq!(result.errors, vec!["Null MX must be the only MX record"]);
+    }
+
+    #[test]
+    fn counts_revoked_dkim_keys_as_none() {
+        let result = null_mx(
+            &[0],
+            0,
+            &spf(vec![SPF_FAIL_ALL.to_string()]),
+            &parse_dmarc(vec![DMARC_REJECT.to_string()]),
+            &dkim(&["v=DKIM1; p=", "v=DKIM1; k=rsa; p="])
+        );
+
+        assert!(result.no_dkim && result.recommended_posture);
+    }
+
+    #[test]
+    fn recommended_posture_needs_every_part() {
+        let spf_loose = spf(vec!["v=spf1 ~all".to_string()]);
+        let dmarc_quarantine = parse_dmarc(vec!["v=DMARC1; p=quarantine".to_string()]);
+        let dmarc_missing = parse_dmarc(Vec::new());
+        let dkim_key = dkim(&["v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo="]);
+
+        let spf_fail_all = spf(vec![SPF_FAIL_ALL.to_string()]);
+        let dmarc_reject = parse_dmarc(vec![DMARC_REJECT.to_string()]);
+        let no_dkim = dkim(&[]);
+
+        let result = null_mx(&[0], 0, &spf(Vec::new()), &dmarc_reject, &no_dkim);
+        assert!(result.valid && !result.spf_fail_all && !result.recommended_posture);
+
+        let result = null_mx(&[0], 0, &spf_loose, &dmarc_reject, &no_dkim);
+        assert!(result.valid && !result.spf_fail_all && !result.recommended_posture);
+
+        let result = null_mx(&[0], 0, &spf_fail_all, &dmarc_missing, &no_dkim);
+        assert!(result.valid && !result.dmarc_reject && !result.recommended_posture);
+
+        let result = null_mx(&[0], 0, &spf_fail_all, &dmarc_quarantine, &no_dkim);
+        assert!(result.valid && !result.dmarc_reject && !result.recommended_posture);
+
+        let result = null_mx(&[0], 0, &spf_fail_all, &dmarc_reject, &dkim_key);
+        assert!(result.valid && !result.no_dkim && !result.recommended_posture);
+    }
+}
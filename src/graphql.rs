@@ -1,16 +1,30 @@
 use crate::types::{
+    Amplification,
+    Autoconfig,
     BrightSchema,
     CheckCAA,
     DnsRecord,
     DnsRecords,
     DomainCheck,
     Email,
+    Ipv6Readiness,
+    MailClients,
     NSRecord,
     QueryRoot,
+    ReverseDns,
+    SpfEvaluation,
+    TtlReport,
     DNSSEC,
 };
+use crate::amplification::check_amplification;
+use crate::autoconfig::check_autoconfig;
 use crate::dns::{ check_caa, check_dnssec, check_ns, dns_records };
-use crate::email::check_email;
+use crate::email::{ check_email, EmailOptions };
+use crate::ipv6::check_ipv6;
+use crate::mua::check_mail_clients;
+use crate::reverse::check_reverse_dns;
+use crate::spf::evaluate_spf;
+use crate::ttl::check_ttl;
 
 use std::sync::Arc;
 use std::convert::Infallible;
@@ -63,15 +77,86 @@ impl DomainCheck {
         Ok(dnssec_result)
     }
 
-    async fn email(&self) -> GqlResult<Email> {
+    async fn email(
+        &self,
+        dkim_selectors: Option<Vec<String>>,
+        smtp_probe: Option<bool>,
+        relay_test: Option<bool>
+    ) -> GqlResult<Email> {
         let domain: Arc<String> = self.domain.clone();
+        let defaults: EmailOptions = EmailOptions::default();
+        let options: EmailOptions = EmailOptions {
+            dkim_selectors: dkim_selectors.unwrap_or_default(),
+            smtp_probe: smtp_probe.unwrap_or(defaults.smtp_probe),
+            relay_test: relay_test.unwrap_or(defaults.relay_test),
+        };
         let email_result: Email = task
-            ::spawn_blocking(move || { check_email(&domain) }).await
+            ::spawn_blocking(move || { check_email(&domain, &options) }).await
             .map_err(|e: JoinError| GqlError::new(e.to_string()))?
             .map_err(|e: Error| GqlError::new(e.to_string()))?;
 
         Ok(email_result)
     }
+
+    async fn mail_clients(&self) -> GqlResult<MailClients> {
+        let domain: Arc<String> = self.domain.clone();
+        let mail_clients_result: MailClients = task
+            ::spawn_blocking(move || { check_mail_clients(&domain) }).await
+            .map_err(|e: JoinError| GqlError::new(e.to_string()))?
+            .map_err(|e: Error| GqlError::new(e.to_string()))?;
+
+        Ok(mail_clients_result)
+    }
+
+    async fn autoconfig(&self) -> GqlResult<Autoconfig> {
+        let domain: Arc<String> = self.domain.clone();
+        let autoconfig_result: Autoconfig = task
+            ::spawn_blocking(move || { check_autoconfig(&domain) }).await
+            .map_err(|e: JoinError| GqlError::new(e.to_string()))?
+            .map_err(|e: Error| GqlError::new(e.to_string()))?;
+
+        Ok(autoconfig_result)
+    }
+
+    async fn ttl(&self) -> GqlResult<TtlReport> {
+        let domain: Arc<String> = self.domain.clone();
+        let ttl_result: TtlReport = task
+            ::spawn_blocking(move || { check_ttl(&domain) }).await
+            .map_err(|e: JoinError| GqlError::new(e.to_string()))?
+            .map_err(|e: Error| GqlError::new(e.to_string()))?;
+
+        Ok(ttl_result)
+    }
+
+    async fn reverse_dns(&self) -> GqlResult<Vec<ReverseDns>> {
+        let domain: Arc<String> = self.domain.clone();
+        let reverse_result: Vec<ReverseDns> = task
+            ::spawn_blocking(move || { check_reverse_dns(&domain) }).await
+            .map_err(|e: JoinError| GqlError::new(e.to_string()))?
+            .map_err(|e: Error| GqlError::new(e.to_string()))?;
+
+        Ok(reverse_result)
+    }
+
+    async fn ipv6(&self) -> GqlResult<Ipv6Readiness> {
+        let domain: Arc<String> = self.domain.clone();
+        let ipv6_result: Ipv6Readiness = task
+            ::spawn_blocking(move || { check_ipv6(&domain) }).await
+            .map_err(|e: JoinError| GqlError::new(e.to_string()))?
+            .map_err(|e: Error| GqlError::new(e.to_string()))?;
+
+        Ok(ipv6_result)
+    }
+
+    async fn amplification(&self) -> GqlResult<Amplification> {
+        let domain: Arc<String> = self.domain.clone();
+        let amplification_result: Amplification = task
+            ::spawn_blocking(move || { check_amplification(&domain) }).await
+            .map_err(|e: JoinError| GqlError::new(e.to_string()))?
+            .map_err(|e: Error| GqlError::new(e.to_string()))?;
+
+        Ok(amplification_result)
+    }
 }
 
 #[Object]
@@ -79,6 +164,23 @@ impl QueryRoot {
     async fn domain_check(&self, domain: String) -> GqlResult<DomainCheck> {
         Ok(DomainCheck { domain: Arc::new(domain) })
     }
+
+    async fn spf_evaluate(
+        &self,
+        domain: String,
+        ip: String,
+        helo: Option<String>,
+        sender: Option<String>
+    ) -> GqlResult<SpfEvaluation> {
+        let helo: String = helo.unwrap_or(domain.clone());
+        let sender: String = sender.unwrap_or_default();
+        let evaluation_result: SpfEvaluation = task
+            ::spawn_blocking(move || { evaluate_spf(&domain, &ip, &helo, &sender) }).await
+            .map_err(|e: JoinError| GqlError::new(e.to_string()))?
+            .map_err(|e: Error| GqlError::new(e.to_string()))?;
+
+        Ok(evaluation_result)
+    }
 }
 
 pub async fn graphql_handler(schema: BrightSchema, req: Request) -> Result<impl Reply, Infallible> {
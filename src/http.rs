@@ -0,0 +1,184 @@
+use crate::tls::{ connect, handshake };
+
+use std::io::{ Read, Write };
+use std::net::{ SocketAddr, TcpStream, ToSocketAddrs };
+
+use anyhow::{ Result, Error };
+use openssl::ssl::SslStream;
+use openssl::x509::X509;
+
+pub const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[doc = "Minimal HTTP/1.1 response, enough for policy files and DNS over HTTPS"]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _value)| key.eq_ignore_ascii_case(name))
+            .map(|(_key, value)| value.as_str())
+    }
+}
+
+#[doc = "HTTPS response together with the certificate chain the server presented, leaf first"]
+pub struct HttpsResponse {
+    pub response: HttpResponse,
+    pub chain: Vec<X509>,
+}
+
+pub fn resolve(host: &str, port: u16) -> Result<SocketAddr> {
+    (host.trim_end_matches('.'), port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| Error::msg(format!("{} has no addresses", host)))
+}
+
+#[doc = "Request over HTTPS to addr with host as SNI and Host header; certificate problems are left to the caller"]
+pub fn https_request(
+    addr: SocketAddr,
+    method: &str,
+    host: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8]
+) -> Result<HttpsResponse> {
+    let host: &str = host.trim_end_matches('.');
+    let mut stream: SslStream<TcpStream> = handshake(connect(addr)?, host)?;
+    let chain: Vec<X509> = stream
+        .ssl()
+        .peer_cert_chain()
+        .map(|chain| chain.iter().map(|cert| cert.to_owned()).collect())
+        .unwrap_or_default();
+
+    let response: HttpResponse = request(&mut stream, method, host, path, headers, body)?;
+
+    Ok(HttpsResponse { response, chain })
+}
+
+pub fn https_get(addr: SocketAddr, host: &str, path: &str) -> Result<HttpsResponse> {
+    https_request(addr, "GET", host, path, &[], &[])
+}
+
+pub fn request<S: Read + Write>(
+    stream: &mut S,
+    method: &str,
+    host: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8]
+) -> Result<HttpResponse> {
+    let mut head: String = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: bright\r\nConnection: close\r\n",
+        method,
+        path,
+        host
+    );
+
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+
+    if !body.is_empty() || method == "POST" {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut raw: Vec<u8> = Vec::new();
+    let mut buffer: [u8; 8192] = [0; 8192];
+
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => {
+                break;
+            }
+            Ok(size) => {
+                raw.extend_from_slice(&buffer[..size]);
+
+                if raw.len() > MAX_BODY_SIZE {
+                    return Err(Error::msg("HTTP response exceeds size limit"));
+                }
+            }
+            // Servers that skip close_notify surface as an error once everything was read
+            Err(_e) if !raw.is_empty() => {
+                break;
+            }
+            Err(e) => {
+                return Err(Error::new(e));
+            }
+        }
+    }
+
+    parse_response(&raw)
+}
+
+fn parse_response(raw: &[u8]) -> Result<HttpResponse> {
+    let header_end: usize = raw
+        .windows(4)
+        .position(|window: &[u8]| window == b"\r\n\r\n")
+        .ok_or_else(|| Error::msg("Malformed HTTP response"))?;
+
+    let head: String = String::from_utf8_lossy(&raw[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+
+    let status: u16 = lines
+        .next()
+        .and_then(|line: &str| line.split_whitespace().nth(1))
+        .and_then(|code: &str| code.parse().ok())
+        .ok_or_else(|| Error::msg("Malformed HTTP status line"))?;
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line: &str| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let mut response: HttpResponse = HttpResponse {
+        status,
+        headers,
+        body: raw[header_end + 4..].to_vec(),
+    };
+
+    let chunked: bool = response
+        .header("Transfer-Encoding")
+        .map(|value: &str| value.eq_ignore_ascii_case("chunked"))
+        .unwrap_or(false);
+
+    if chunked {
+        response.body = decode_chunked(&response.body)?;
+    } else if let Some(length) = response.header("Content-Length").and_then(|v| v.parse().ok()) {
+        response.body.truncate(length);
+    }
+
+    Ok(response)
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded: Vec<u8> = Vec::new();
+
+    loop {
+        let line_end: usize = body
+            .windows(2)
+            .position(|window: &[u8]| window == b"\r\n")
+            .ok_or_else(|| Error::msg("Malformed chunked body"))?;
+        let size_line: String = String::from_utf8_lossy(&body[..line_end]).to_string();
+        let size: usize = usize::from_str_radix(size_line.split(';').next().unwrap_or("").trim(), 16)?;
+
+        body = &body[line_end + 2..];
+        if size == 0 || body.len() < size {
+            break;
+        }
+
+        decoded.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or(&[]);
+    }
+
+    Ok(decoded)
+}
@@ -0,0 +1,297 @@
+use crate::types::{ Ipv6Address, Ipv6Readiness };
+use crate::query::{ lookup_rdata, nameserver_names };
+use crate::reverse::ptr_record;
+
+use std::net::{ IpAddr, Ipv6Addr, SocketAddr, TcpStream };
+use std::prelude::v1::Result as stdResult;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use hickory_resolver::Resolver;
+use hickory_resolver::lookup::Lookup;
+use hickory_resolver::error::ResolveError;
+use hickory_resolver::proto::rr::{ RData, RecordType };
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const WEB_PORTS: [u16; 2] = [443, 80];
+const DNS_PORTS: [u16; 1] = [53];
+const SMTP_PORTS: [u16; 1] = [25];
+
+fn aaaa_addresses(resolver: &Resolver, host: &str) -> Vec<Ipv6Addr> {
+    let result: stdResult<Lookup, ResolveError> = resolver.lookup(host, RecordType::AAAA);
+
+    match result {
+        Ok(lookup) =>
+            lookup
+                .iter()
+                .filter_map(|rdata: &RData| {
+                    match rdata.ip_addr() {
+                        Some(IpAddr::V6(ipv6)) => Some(ipv6),
+                        _ => None,
+                    }
+                })
+                .collect(),
+        Err(_e) => Vec::new(),
+    }
+}
+
+fn tcp_reachable(ip: Ipv6Addr, port: u16) -> bool {
+    TcpStream::connect_timeout(&SocketAddr::new(IpAddr::V6(ip), port), CONNECT_TIMEOUT).is_ok()
+}
+
+fn ipv6_address(source: &str, host: &str, ip: Ipv6Addr, ports: &[u16]) -> Ipv6Address {
+    Ipv6Address {
+        source: source.to_string(),
+        host: host.to_string(),
+        ip: ip.to_string(),
+        port: ports[0],
+        reachable: false,
+        ptr: String::new(),
+        ptr_present: false,
+        error: String::new(),
+    }
+}
+
+#[doc = "Connect to every address in parallel; port becomes the first of its ports that answered"]
+fn probe_addresses(resolver: &Resolver, addresses: &mut [Ipv6Address], targets: &[(Ipv6Addr, &[u16])]) {
+    thread::scope(|scope| {
+        for (address, (ip, ports)) in addresses.iter_mut().zip(targets) {
+            scope.spawn(move || {
+                if let Some(port) = ports.iter().copied().find(|port: &u16| tcp_reachable(*ip, *port)) {
+                    address.port = port;
+                    address.reachable = true;
+                }
+
+                match ptr_record(resolver, IpAddr::V6(*ip)) {
+                    Ok(ptr) => {
+                        address.ptr_present = !ptr.is_empty();
+                        address.ptr = ptr;
+                    }
+                    Err(e) => {
+                        address.error = format!("PTR lookup failed: {}", e);
+                    }
+                }
+            });
+        }
+    });
+}
+
+#[doc = "Readiness summary over the probed addresses; exchanges are the MX hosts, with or without AAAA records"]
+fn readiness(exchanges: &[String], addresses: Vec<Ipv6Address>) -> Ipv6Readiness {
+    let has_source = |source: &str| addresses.iter().any(|address: &Ipv6Address| address.source == source);
+
+    let mut reachable_nameservers: Vec<&str> = addresses
+        .iter()
+        .filter(|address: &&Ipv6Address| address.source == "ns" && address.reachable)
+        .map(|address: &Ipv6Address| address.host.as_str())
+        .collect();
+    reachable_nameservers.sort_unstable();
+    reachable_nameservers.dedup();
+
+    let apex_aaaa: bool = has_source("apex");
+    let www_aaaa: bool = has_source("www");
+    let ns_ipv6_reachable: u32 = reachable_nameservers.len() as u32;
+    let ns_ipv6_sufficient: bool = ns_ipv6_reachable >= 2;
+    let mx_aaaa: bool =
+        !exchanges.is_empty() &&
+        exchanges.iter().all(|exchange: &String| {
+            addresses.iter().any(|address: &Ipv6Address| address.source == "mx" && &address.host == exchange)
+        });
+    let all_reachable: bool = addresses.iter().all(|address: &Ipv6Address| address.reachable);
+    let ptr_present: bool = addresses.iter().all(|address: &Ipv6Address| address.ptr_present);
+    let ready: bool =
+        apex_aaaa && www_aaaa && ns_ipv6_sufficient && mx_aaaa && all_reachable && ptr_present;
+
+    Ipv6Readiness {
+        apex_aaaa,
+        www_aaaa,
+        ns_ipv6_reachable,
+        ns_ipv6_sufficient,
+        mx_aaaa,
+        all_reachable,
+        ptr_present,
+        ready,
+        addresses,
+    }
+}
+
+pub fn check_ipv6(domain: &str) -> Result<Ipv6Readiness> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let mut addresses: Vec<Ipv6Address> = Vec::new();
+    let mut targets: Vec<(Ipv6Addr, &[u16])> = Vec::new();
+
+    let www_domain: String = format!("www.{}", domain);
+
+    // Web hosts count as reachable on HTTPS or HTTP, the port that answered is reported
+    for (source, host) in [("apex", domain), ("www", www_domain.as_str())] {
+        for ip in aaaa_addresses(&resolver, host) {
+            addresses.push(ipv6_address(source, host, ip, &WEB_PORTS));
+            targets.push((ip, &WEB_PORTS));
+        }
+    }
+
+    // Only port 53 reachability is needed here, not the full nameserver checks
+    let nameservers: Vec<String> = nameserver_names(&resolver, domain).unwrap_or_default();
+
+    for nsdomain in &nameservers {
+        for ip in aaaa_addresses(&resolver, nsdomain) {
+            addresses.push(ipv6_address("ns", nsdomain, ip, &DNS_PORTS));
+            targets.push((ip, &DNS_PORTS));
+        }
+    }
+
+    let exchanges: Vec<String> = lookup_rdata(&resolver, domain, RecordType::MX)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|rdata: &RData| {
+            match rdata {
+                RData::MX(mx) => Some(mx.exchange().to_string()),
+                _ => None,
+            }
+        })
+        .filter(|exchange: &String| exchange != ".")
+        .collect();
+
+    for exchange in &exchanges {
+        for ip in aaaa_addresses(&resolver, exchange) {
+            addresses.push(ipv6_address("mx", exchange, ip, &SMTP_PORTS));
+            targets.push((ip, &SMTP_PORTS));
+        }
+    }
+
+    probe_addresses(&resolver, &mut addresses, &targets);
+
+    Ok(readiness(&exchanges, addresses))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ ipv6_address, probe_addresses, readiness, DNS_PORTS, SMTP_PORTS, WEB_PORTS };
+    use crate::types::Ipv6Address;
+
+    use std::net::{ Ipv6Addr, TcpListener };
+
+    use hickory_resolver::Resolver;
+    use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+
+    fn address(source: &str, host: &str, ip: &str, reachable: bool, ptr: &str) -> Ipv6Address {
+        let ports: &[u16] = match source {
+            "ns" => &DNS_PORTS,
+            "mx" => &SMTP_PORTS,
+            _ => &WEB_PORTS,
+        };
+        let mut address: Ipv6Address = ipv6_address(source, host, ip.parse::<Ipv6Addr>().unwrap(), ports);
+        address.reachable = reachable;
+        address.ptr = ptr.to_string();
+        address.ptr_present = !ptr.is_empty();
+
+        address
+    }
+
+    fn ready_addresses() -> Vec<Ipv6Address> {
+        vec![
+            address("apex", "example.test", "2001:db8::1", true, "web.example.test."),
+            address("www", "www.example.test", "2001:db8::1", true, "web.example.test."),
+            address("ns", "ns1.example.test.", "2001:db8::53", true, "ns1.example.test."),
+            address("ns", "ns2.example.test.", "2001:db8:1::53", true, "ns2.example.test."),
+            address("mx", "mx.example.test.", "2001:db8::25", true, "mx.example.test.")
+        ]
+    }
+
+    #[test]
+    fn ready_with_everything_in_place() {
+        let result = readiness(&["mx.example.test.".to_string()], ready_addresses());
+
+        assert!(result.apex_aaaa && result.www_aaaa && result.mx_aaaa);
+        assert_eq!(result.ns_ipv6_reachable, 2);
+        assert!(result.ns_ipv6_sufficient && result.all_reachable && result.ptr_present);
+        assert!(result.ready);
+    }
+
+    #[test]
+    fn counts_nameservers_not_addresses() {
+        let mut addresses: Vec<Ipv6Address> = ready_addresses();
+        addresses.retain(|address: &Ipv6Address| address.host != "ns2.example.test.");
+        addresses.push(address("ns", "ns1.example.test.", "2001:db8:2::53", true, "ns1.example.test."));
+
+        let result = readiness(&["mx.example.test.".to_string()], addresses);
+
+        assert_eq!(result.ns_ipv6_reachable, 1);
+        assert!(!result.ns_ipv6_sufficient && !result.ready);
+    }
+
+    #[test]
+    fn unreachable_nameserver_is_not_counted() {
+        let mut addresses: Vec<Ipv6Address> = ready_addresses();
+        addresses[3].reachable = false;
+
+        let result = readiness(&["mx.example.test.".to_string()], addresses);
+
+        assert_eq!(result.ns_ipv6_reachable, 1);
+        assert!(!result.ns_ipv6_sufficient && !result.all_reachable && !result.ready);
+    }
+
+    #[test]
+    fn every_mx_needs_aaaa() {
+        let exchanges: Vec<String> = vec!["mx.example.test.".to_string(), "mx2.example.test.".to_string()];
+        let result = readiness(&exchanges, ready_addresses());
+        assert!(!result.mx_aaaa && !result.ready);
+
+        let result = readiness(&[], ready_addresses());
+        assert!(!result.mx_aaaa && !result.ready);
+    }
+
+    #[test]
+    fn missing_ptr_blocks_readiness() {
+        let mut addresses: Vec<Ipv6Address> = ready_addresses();
+        addresses[4].ptr = String::new();
+        addresses[4].ptr_present = false;
+
+        let result = readiness(&["mx.example.test.".to_string()], addresses);
+
+        assert!(result.all_reachable && !result.ptr_present && !result.ready);
+    }
+
+    #[test]
+    fn missing_web_aaaa() {
+        let mut addresses: Vec<Ipv6Address> = ready_addresses();
+        addresses.retain(|address: &Ipv6Address| address.source != "www");
+
+        let result = readiness(&["mx.example.test.".to_string()], addresses);
+
+        assert!(result.apex_aaaa && !result.www_aaaa && !result.ready);
+    }
+
+    #[test]
+    fn reports_first_port_until_one_answers() {
+        assert_eq!(address("apex", "example.test", "2001:db8::1", false, "").port, 443);
+        assert_eq!(address("mx", "mx.example.test.", "2001:db8::25", false, "").port, 25);
+    }
+
+    #[test]
+    fn reports_port_that_answered() {
+        let closed: u16 = TcpListener::bind("[::1]:0").unwrap().local_addr().unwrap().port();
+        let listener: TcpListener = TcpListener::bind("[::1]:0").unwrap();
+        let open: u16 = listener.local_addr().unwrap().port();
+        let ports: [u16; 2] = [closed, open];
+
+        let resolver: Resolver = Resolver::new(ResolverConfig::new(), ResolverOpts::default()).unwrap();
+        let mut addresses: Vec<Ipv6Address> = vec![
+            ipv6_address("apex", "example.test", Ipv6Addr::LOCALHOST, &ports),
+            ipv6_address("www", "www.example.test", Ipv6Addr::LOCALHOST, &[closed])
+        ];
+
+        probe_addresses(
+            &resolver,
+            &mut addresses,
+            &[(Ipv6Addr::LOCALHOST, &ports), (Ipv6Addr::LOCALHOST, &[closed])]
+        );
+
+        assert!(addresses[0].reachable);
+        assert_eq!(addresses[0].port, open);
+        assert!(!addresses[1].reachable);
+        assert_eq!(addresses[1].port, closed);
+    }
+}
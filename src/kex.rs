@@ -0,0 +1,221 @@
+use crate::types::KeyExchange;
+use crate::ciphers::{ ALL_CIPHERS, TLS13_CIPHERSUITES };
+use crate::tls::{ handshake_with, hex, scan_builder };
+
+use std::net::TcpStream;
+
+use anyhow::{ Result, Error };
+use openssl::bn::BigNum;
+use openssl::dh::Dh;
+use openssl::pkey::{ Id, PKey, Public };
+use openssl::sha::sha256;
+use openssl::ssl::{ SslConnectorBuilder, SslStream, SslVersion };
+
+const EC_GROUPS: &str = "X25519:X448:P-256:P-384:P-521";
+
+// SHA-256 of the RFC 7919 primes, OpenSSL has no getters for them
+const FFDHE_PRIMES: [(&str, &str); 5] = [
+    ("ffdhe2048", "9cd3b7f336872f46c09428d1bbc19877a4d440512cda8d1c1cf0cd6e33698966"),
+    ("ffdhe3072", "0eaf67db3a839156d5013494a5318a772b5697d270d721f37f092efc69ea5a17"),
+    ("ffdhe4096", "4648414224ac881b3d0dc59b466f96d06a558278776807797ecf1f66ff397b3e"),
+    ("ffdhe6144", "227ac9066b3ddd9e193670cda2388fa884f65ba0cf98b742d1fe77a6687c79c7"),
+    ("ffdhe8192", "770b14efaf6f049929c523113b3fa99a8d11dab1b18af3609590122075d19833"),
+];
+
+#[doc = "Well-known DH primes as (name, weak); RFC 5114 groups count as weak because of their small subgroups"]
+fn known_prime(prime: &[u8]) -> Option<(String, bool)> {
+    let fingerprint: String = hex(&sha256(prime));
+
+    if let Some((name, _)) = FFDHE_PRIMES.iter().find(|(_, hash)| *hash == fingerprint) {
+        return Some((name.to_string(), false));
+    }
+
+    let rfc_primes: Vec<(&str, Option<Vec<u8>>, bool)> = vec![
+        ("RFC 2409 group 1", BigNum::get_rfc2409_prime_768().ok().map(|p| p.to_vec()), true),
+        ("RFC 2409 group 2", BigNum::get_rfc2409_prime_1024().ok().map(|p| p.to_vec()), true),
+        ("RFC 3526 group 5", BigNum::get_rfc3526_prime_1536().ok().map(|p| p.to_vec()), true),
+        ("RFC 3526 group 14", BigNum::get_rfc3526_prime_2048().ok().map(|p| p.to_vec()), false),
+        ("RFC 3526 group 15", BigNum::get_rfc3526_prime_3072().ok().map(|p| p.to_vec()), false),
+        ("RFC 3526 group 16", BigNum::get_rfc3526_prime_4096().ok().map(|p| p.to_vec()), false),
+        ("RFC 3526 group 17", BigNum::get_rfc3526_prime_6144().ok().map(|p| p.to_vec()), false),
+        ("RFC 3526 group 18", BigNum::get_rfc3526_prime_8192().ok().map(|p| p.to_vec()), false),
+        (
+            "RFC 5114 1024-bit MODP with 160-bit subgroup",
+            Dh::get_1024_160().ok().map(|dh| dh.prime_p().to_vec()),
+            true,
+        ),
+        (
+            "RFC 5114 2048-bit MODP with 224-bit subgroup",
+            Dh::get_2048_224().ok().map(|dh| dh.prime_p().to_vec()),
+            true,
+        ),
+        (
+            "RFC 5114 2048-bit MODP with 256-bit subgroup",
+            Dh::get_2048_256().ok().map(|dh| dh.prime_p().to_vec()),
+            true,
+        ),
+    ];
+
+    rfc_primes
+        .into_iter()
+        .find(|(_, known, _)| known.as_deref() == Some(prime))
+        .map(|(name, _, weak)| (name.to_string(), weak))
+}
+
+#[doc = "Name and size of the server's ephemeral key: curve, X25519/X448, ffdhe group or plain DH"]
+fn group(key: &PKey<Public>) -> (String, u32) {
+    let bits: u32 = key.bits();
+
+    let name: String = match key.id() {
+        Id::X25519 => "X25519".to_string(),
+        Id::X448 => "X448".to_string(),
+        Id::EC =>
+            key
+                .ec_key()
+                .ok()
+                .and_then(|ec| ec.group().curve_name())
+                .and_then(|nid| nid.short_name().ok())
+                .unwrap_or("EC")
+                .to_string(),
+        Id::DH | Id::DHX =>
+            key
+                .dh()
+                .ok()
+                .and_then(|dh| known_prime(&dh.prime_p().to_vec()))
+                .map(|(name, _)| name)
+                .unwrap_or("DH".to_string()),
+        _ => "unknown".to_string(),
+    };
+
+    (name, bits)
+}
+
+fn peer_key(
+    open: &dyn Fn() -> Result<TcpStream>,
+    server_name: &str,
+    builder: SslConnectorBuilder
+) -> Result<PKey<Public>> {
+    let stream: SslStream<TcpStream> = handshake_with(&builder.build(), open()?, server_name)?;
+
+    stream
+        .ssl()
+        .peer_tmp_key()
+        .map_err(|_e| Error::msg("Server sent no ephemeral key"))
+}
+
+#[doc = "Negotiated key exchange group plus separate DHE and ECDHE handshakes to size each of them"]
+pub fn check_key_exchange(server_name: &str, open: &dyn Fn() -> Result<TcpStream>) -> KeyExchange {
+    let mut kex: KeyExchange = KeyExchange {
+        negotiated_group: String::new(),
+        negotiated_bits: 0,
+        dhe_supported: false,
+        dhe_bits: 0,
+        dhe_prime: String::new(),
+        dhe_prime_weak: false,
+        dhe_sufficient: true,
+        ecdhe_supported: false,
+        ecdhe_curve: String::new(),
+        ecdhe_bits: 0,
+        ecdhe_sufficient: true,
+        error: String::new(),
+    };
+    let suites: String = TLS13_CIPHERSUITES.join(":");
+
+    match
+        scan_builder(SslVersion::TLS1, SslVersion::TLS1_3, ALL_CIPHERS, &suites).and_then(
+            |builder: SslConnectorBuilder| peer_key(open, server_name, builder)
+        )
+    {
+        Ok(key) => {
+            (kex.negotiated_group, kex.negotiated_bits) = group(&key);
+        }
+        Err(e) => {
+            kex.error = e.to_string();
+        }
+    }
+
+    // TLS 1.3 servers rarely offer ffdhe groups, so finite field DHE is sized over TLS 1.2 and below
+    if
+        let Ok(key) = scan_builder(SslVersion::TLS1, SslVersion::TLS1_2, "kDHE", "").and_then(
+            |builder: SslConnectorBuilder| peer_key(open, server_name, builder)
+        )
+    {
+        kex.dhe_supported = true;
+        kex.dhe_bits = key.bits();
+
+        if let Some((name, weak)) = key.dh().ok().and_then(|dh| known_prime(&dh.prime_p().to_vec())) {
+            kex.dhe_prime = name;
+            kex.dhe_prime_weak = weak;
+        }
+
+        kex.dhe_sufficient = kex.dhe_bits >= 2048 && !kex.dhe_prime_weak;
+    }
+
+    let ecdhe: Result<PKey<Public>> = scan_builder(
+        SslVersion::TLS1,
+        SslVersion::TLS1_3,
+        "kECDHE",
+        &suites
+    ).and_then(|mut builder: SslConnectorBuilder| {
+        builder.set_groups_list(EC_GROUPS)?;
+        peer_key(open, server_name, builder)
+    });
+
+    if let Ok(key) = ecdhe {
+        kex.ecdhe_supported = true;
+        (kex.ecdhe_curve, kex.ecdhe_bits) = group(&key);
+
+        // X25519 keys are 253 bits but give the same 128-bit security as P-256
+        kex.ecdhe_sufficient = kex.ecdhe_bits >= 256 || key.id() == Id::X25519 || key.id() == Id::X448;
+    }
+
+    kex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ check_key_exchange, known_prime };
+    use crate::test_support::acceptor;
+    use crate::tls::connect;
+
+    use std::net::{ SocketAddr, TcpListener };
+    use std::thread;
+
+    use openssl::bn::BigNum;
+    use openssl::dh::Dh;
+    use openssl::ssl::SslAcceptor;
+
+    #[test]
+    fn detects_known_primes() {
+        let oakley: Vec<u8> = BigNum::get_rfc2409_prime_1024().unwrap().to_vec();
+        assert_eq!(known_prime(&oakley), Some(("RFC 2409 group 2".to_string(), true)));
+
+        let rfc5114: Vec<u8> = Dh::get_2048_256().unwrap().prime_p().to_vec();
+        assert!(known_prime(&rfc5114).unwrap().1);
+
+        assert_eq!(known_prime(&[0xff; 256]), None);
+    }
+
+    #[test]
+    fn sizes_key_exchange_of_stand_in() {
+        let acceptor: SslAcceptor = acceptor("www.example.test");
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+
+        // Negotiated, DHE and ECDHE handshakes
+        thread::spawn(move || {
+            for stream in listener.incoming().take(3) {
+                let _ = acceptor.accept(stream.unwrap());
+            }
+        });
+
+        let kex = check_key_exchange("www.example.test", &(|| connect(addr)));
+
+        assert!(kex.error.is_empty(), "{}", kex.error);
+        assert_eq!(kex.negotiated_group, "X25519");
+        assert!(kex.ecdhe_supported && kex.ecdhe_sufficient);
+        // OpenSSL picks the RFC 7919 group matching the certificate key size
+        assert!(kex.dhe_supported && kex.dhe_sufficient);
+        assert_eq!((kex.dhe_bits, kex.dhe_prime.as_str()), (2048, "ffdhe2048"));
+    }
+}
@@ -1,7 +1,32 @@
 mod types;
+mod amplification;
+mod autoconfig;
+mod bimi;
+mod certificate;
+mod ciphers;
+mod cookies;
+mod dane;
+mod dkim;
+mod dmarc;
 mod dns;
 mod email;
+mod http;
+mod ipv6;
+mod kex;
+mod mtasts;
+mod mua;
+mod psl;
+mod query;
+mod reverse;
+mod smtp;
+mod spf;
+mod tls;
+mod tlsrpt;
+mod transport;
+mod ttl;
 mod graphql;
+#[cfg(test)]
+mod test_support;
 use types::QueryRoot;
 use graphql::graphql_handler;
 
@@ -0,0 +1,293 @@
+use crate::types::{ CertificateChain, MtaSts };
+use crate::certificate::{ check_chain, name_matches };
+use crate::http::{ https_get, resolve, HttpsResponse };
+use crate::kex::check_key_exchange;
+use crate::query::txt_records;
+use crate::tls::connect;
+
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+
+pub const POLICY_PATH: &str = "/.well-known/mta-sts.txt";
+const MAX_AGE_LIMIT: u64 = 31557600;
+
+#[doc = "Parsed mta-sts.txt policy, see RFC 8461 section 3.2"]
+#[derive(Debug, Default)]
+pub struct Policy {
+    pub version: String,
+    pub mode: String,
+    pub max_age: Option<u64>,
+    pub mx: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+#[doc = "Policy id of a v=STSv1 TXT record, None if the record is not a valid one"]
+pub fn parse_record(record: &str) -> Option<String> {
+    let mut fields = record.split(';').map(|field: &str| field.trim());
+
+    if fields.next() != Some("v=STSv1") {
+        return None;
+    }
+
+    fields
+        .filter_map(|field: &str| field.split_once('='))
+        .find(|(key, _value)| key.trim() == "id")
+        .map(|(_key, value)| value.trim().to_string())
+        .filter(|id: &String| {
+            (1..=32).contains(&id.len()) && id.chars().all(|c: char| c.is_ascii_alphanumeric())
+        })
+}
+
+fn valid_pattern(pattern: &str) -> bool {
+    let host: &str = pattern.strip_prefix("*.").unwrap_or(pattern);
+
+    host.contains('.') &&
+        host
+            .split('.')
+            .all(|label: &str| {
+                !label.is_empty() &&
+                    label.len() <= 63 &&
+                    label.chars().all(|c: char| c.is_ascii_alphanumeric() || c == '-')
+            })
+}
+
+pub fn parse_policy(body: &str) -> Policy {
+    let mut policy: Policy = Policy::default();
+
+    for line in body.lines() {
+        let line: &str = line.trim_end_matches('\r');
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => {
+                policy.errors.push(format!("Malformed line: {}", line));
+                continue;
+            }
+        };
+
+        match key {
+            "version" => {
+                policy.version = value.to_string();
+            }
+            "mode" => {
+                policy.mode = value.to_string();
+            }
+            "max_age" => {
+                policy.max_age = value.parse().ok();
+                if policy.max_age.is_none() {
+                    policy.errors.push(format!("Invalid max_age {}", value));
+                }
+            }
+            "mx" => {
+                if !valid_pattern(value) {
+                    policy.errors.push(format!("Invalid mx pattern {}", value));
+                }
+                policy.mx.push(value.to_string());
+            }
+            // Unknown keys are allowed for future extensions
+            _ => {}
+        }
+    }
+
+    if policy.version != "STSv1" {
+        policy.errors.push("version must be STSv1".to_string());
+    }
+    if !["enforce", "testing", "none"].contains(&policy.mode.as_str()) {
+        policy.errors.push("mode must be enforce, testing or none".to_string());
+    }
+    match policy.max_age {
+        Some(max_age) if max_age > MAX_AGE_LIMIT => {
+            policy.errors.push(format!("max_age above {}", MAX_AGE_LIMIT));
+        }
+        None if !policy.errors.iter().any(|error: &String| error.starts_with("Invalid max_age")) => {
+            policy.errors.push("max_age is missing".to_string());
+        }
+        _ => {}
+    }
+    if policy.mx.is_empty() && policy.mode != "none" {
+        policy.errors.push("No mx patterns".to_string());
+    }
+
+    policy
+}
+
+#[doc = "Fetch the policy from addr, validate it with its certificate and match the MX hosts against it"]
+pub fn fetch_policy(mta_sts: &mut MtaSts, domain: &str, addr: SocketAddr, mx_names: &[String]) {
+    let host: String = format!("mta-sts.{}", domain.trim_end_matches('.'));
+
+    let fetched: HttpsResponse = match https_get(addr, &host, POLICY_PATH) {
+        Ok(fetched) => fetched,
+        Err(e) => {
+            mta_sts.error = e.to_string();
+            return;
+        }
+    };
+
+    let certificate: CertificateChain = check_chain(&addr.ip().to_string(), &host, &fetched.chain);
+    mta_sts.certificate_valid = certificate.trusted && certificate.hostname_match;
+    mta_sts.certificate = Some(certificate);
+    mta_sts.http_status = fetched.response.status;
+    mta_sts.content_type = fetched.response.header("Content-Type").unwrap_or("").to_string();
+
+    // Redirects must not be followed, anything but 200 means no policy
+    if fetched.response.status != 200 {
+        mta_sts.error = format!("Policy answered HTTP {}", fetched.response.status);
+        return;
+    }
+
+    mta_sts.policy_fetched = true;
+
+    let policy: Policy = parse_policy(&String::from_utf8_lossy(&fetched.response.body));
+    let mut errors: Vec<String> = policy.errors;
+
+    if !mta_sts.content_type.starts_with("text/plain") {
+        errors.push(format!("Content-Type {} is not text/plain", mta_sts.content_type));
+    }
+
+    mta_sts.version = policy.version;
+    mta_sts.mode = policy.mode;
+    mta_sts.max_age = policy.max_age.unwrap_or(0);
+    mta_sts.policy_valid = errors.is_empty();
+    mta_sts.policy_errors = errors;
+
+    mta_sts.unmatched_mx = mx_names
+        .iter()
+        .filter(|mx: &&String| !policy.mx.iter().any(|pattern: &String| name_matches(pattern, mx)))
+        .cloned()
+        .collect();
+    mta_sts.mx_patterns = policy.mx;
+    mta_sts.mx_matched = !mx_names.is_empty() && mta_sts.unmatched_mx.is_empty();
+
+    mta_sts.enforced =
+        mta_sts.mode == "enforce" &&
+        mta_sts.record_valid &&
+        mta_sts.policy_valid &&
+        mta_sts.certificate_valid &&
+        mta_sts.mx_matched;
+}
+
+fn mta_sts(domain: &str) -> MtaSts {
+    MtaSts {
+        records: Vec::new(),
+        record_valid: false,
+        id: String::new(),
+        policy_url: format!("https://mta-sts.{}{}", domain, POLICY_PATH),
+        policy_fetched: false,
+        http_status: 0,
+        content_type: String::new(),
+        version: String::new(),
+        mode: String::new(),
+        max_age: 0,
+        mx_patterns: Vec::new(),
+        policy_valid: false,
+        policy_errors: Vec::new(),
+        certificate: None,
+        certificate_valid: false,
+        key_exchange: None,
+        mx_matched: false,
+        unmatched_mx: Vec::new(),
+        enforced: false,
+        error: String::new(),
+    }
+}
+
+#[doc = "MTA-STS record and policy of a domain; a failed lookup is reported in error"]
+pub fn check_mta_sts(domain: &str, mx_names: &[String]) -> MtaSts {
+    let domain: &str = domain.trim_end_matches('.');
+    let mut mta_sts: MtaSts = mta_sts(domain);
+
+    if let Err(e) = lookup_mta_sts(domain, mx_names, &mut mta_sts) {
+        mta_sts.error = e.to_string();
+    }
+
+    mta_sts
+}
+
+fn lookup_mta_sts(domain: &str, mx_names: &[String], mta_sts: &mut MtaSts) -> Result<()> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+
+    mta_sts.records = txt_records(&resolver, &format!("_mta-sts.{}", domain))?
+        .into_iter()
+        .filter(|record: &String| record.starts_with("v=STSv1"))
+        .collect();
+
+    // More than one STSv1 record means senders treat the domain as having none
+    if let [record] = mta_sts.records.as_slice() {
+        if let Some(id) = parse_record(record) {
+            mta_sts.record_valid = true;
+            mta_sts.id = id;
+        }
+    }
+
+    if mta_sts.records.is_empty() {
+        return Ok(());
+    }
+
+    let host: String = format!("mta-sts.{}", domain);
+    let addr: SocketAddr = resolve(&host, 443)?;
+    fetch_policy(mta_sts, domain, addr, mx_names);
+
+    if mta_sts.certificate.is_some() {
+        mta_sts.key_exchange = Some(check_key_exchange(&host, &(|| connect(addr))));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ fetch_policy, mta_sts, parse_policy, parse_record };
+    use crate::types::MtaSts;
+    use crate::test_support::stand_in;
+
+    use std::net::SocketAddr;
+
+    #[test]
+    fn parses_record() {
+        assert_eq!(parse_record("v=STSv1; id=20240101T000000;"), Some("20240101T000000".to_string()));
+        assert_eq!(parse_record("v=STSv1; id=has-dash"), None);
+        assert_eq!(parse_record("v=spf1 -all"), None);
+    }
+
+    #[test]
+    fn validates_policy() {
+        let policy = parse_policy("version: STSv1\r\nmode: enforce\r\nmx: *.example.test\r\nmax_age: 604800\r\n");
+        assert!(policy.errors.is_empty());
+        assert_eq!(policy.mx, vec!["*.example.test"]);
+
+        let policy = parse_policy("version: STSv1\nmode: strict\nmax_age: 99999999\n");
+        assert_eq!(policy.errors.len(), 3);
+    }
+
+    #[test]
+    fn fetches_policy_from_stand_in() {
+        let addr: SocketAddr = stand_in(
+            "mta-sts.example.test",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nversion: STSv1\r\nmode: enforce\r\nmx: mx1.example.test\r\nmax_age: 86400\r\n"
+        );
+        let mut mta_sts: MtaSts = mta_sts("example.test");
+        mta_sts.record_valid = true;
+
+        fetch_policy(
+            &mut mta_sts,
+            "example.test",
+            addr,
+            &["mx1.example.test.".to_string(), "mx2.example.test.".to_string()]
+        );
+
+        assert!(mta_sts.policy_fetched, "{}", mta_sts.error);
+        assert!(mta_sts.policy_valid, "{:?}", mta_sts.policy_errors);
+        assert_eq!(mta_sts.mode, "enforce");
+        assert_eq!(mta_sts.max_age, 86400);
+        assert_eq!(mta_sts.unmatched_mx, vec!["mx2.example.test."]);
+        assert!(!mta_sts.certificate_valid);
+        assert!(!mta_sts.enforced);
+    }
+}
@@ -0,0 +1,515 @@
+use crate::types::{ CertificateChain, KeyExchange, MailClients, MailEndpoint };
+use crate::certificate::check_chain;
+use crate::kex::check_key_exchange;
+use crate::query::{ addresses, lookup_rdata };
+use crate::smtp::{ command, connect, read_line, read_reply, SmtpConfig, SmtpReply };
+use crate::tls::handshake;
+
+use std::io::{ Read, Write };
+use std::net::{ IpAddr, TcpStream };
+
+use anyhow::{ Result, Error };
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+use hickory_resolver::proto::rr::{ RData, RecordType };
+use openssl::ssl::SslStream;
+use openssl::x509::X509;
+
+#[doc = "RFC 6186 and RFC 8314 services: SRV label, protocol, default port and whether TLS is implicit"]
+const SERVICES: [(&str, &str, u16, bool); 6] = [
+    ("submissions", "smtp", 465, true),
+    ("submission", "smtp", 587, false),
+    ("imaps", "imap", 993, true),
+    ("imap", "imap", 143, false),
+    ("pop3s", "pop3", 995, true),
+    ("pop3", "pop3", 110, false),
+];
+
+fn conventional_hosts(protocol: &str) -> &'static [&'static str] {
+    match protocol {
+        "smtp" => &["smtp", "mail"],
+        "imap" => &["imap", "mail"],
+        _ => &["pop3", "pop", "mail"],
+    }
+}
+
+#[doc = "Untagged greeting: 220 for SMTP, * OK for IMAP and +OK for POP3"]
+fn greeting<S: Read>(protocol: &str, stream: &mut S) -> Result<()> {
+    let ok: bool = match protocol {
+        "smtp" => read_reply(stream)?.code == 220,
+        "imap" => read_line(stream)?.starts_with("* OK"),
+        _ => read_line(stream)?.starts_with("+OK"),
+    };
+
+    if !ok {
+        return Err(Error::msg(format!("Unexpected {} greeting", protocol.to_uppercase())));
+    }
+
+    Ok(())
+}
+
+#[doc = "Lines of a tagged IMAP exchange, up to and including the tagged completion"]
+fn imap_command<S: Read + Write>(stream: &mut S, tag: &str, line: &str) -> Result<Vec<String>> {
+    stream.write_all(format!("{} {}\r\n", tag, line).as_bytes())?;
+    stream.flush()?;
+
+    let mut lines: Vec<String> = Vec::new();
+
+    loop {
+        let line: String = read_line(stream)?;
+        let done: bool = line.starts_with(&format!("{} ", tag));
+        lines.push(line);
+
+        if done {
+            return Ok(lines);
+        }
+    }
+}
+
+fn capabilities<S: Read + Write>(protocol: &str, stream: &mut S, config: &SmtpConfig) -> Result<Vec<String>> {
+    match protocol {
+        "smtp" => {
+            let ehlo: SmtpReply = command(stream, &format!("EHLO {}", config.ehlo_name))?;
+            if ehlo.code != 250 {
+                return Err(Error::msg(format!("EHLO rejected with {}", ehlo.code)));
+            }
+
+            Ok(ehlo.lines.into_iter().skip(1).collect())
+        }
+        "imap" => {
+            Ok(
+                imap_command(stream, "a1", "CAPABILITY")?
+                    .iter()
+                    .filter_map(|line: &String| line.strip_prefix("* CAPABILITY "))
+                    .flat_map(|capabilities: &str| capabilities.split_whitespace())
+                    .map(|capability: &str| capability.to_string())
+                    .collect()
+            )
+        }
+        _ => {
+            stream.write_all(b"CAPA\r\n")?;
+            stream.flush()?;
+
+            // Servers without CAPA answer -ERR and are left without capabilities
+            if !read_line(stream)?.starts_with("+OK") {
+                return Ok(Vec::new());
+            }
+
+            let mut capabilities: Vec<String> = Vec::new();
+            loop {
+                let line: String = read_line(stream)?;
+                if line == "." {
+                    return Ok(capabilities);
+                }
+                capabilities.push(line);
+            }
+        }
+    }
+}
+
+#[doc = "Whether credentials could be sent in the clear: any SMTP AUTH, IMAP LOGIN unless LOGINDISABLED, POP3 USER or SASL PLAIN/LOGIN"]
+pub fn plaintext_auth(protocol: &str, capabilities: &[String]) -> bool {
+    let upper: Vec<String> = capabilities
+        .iter()
+        .map(|capability: &String| capability.to_uppercase())
+        .collect();
+
+    match protocol {
+        "smtp" => upper.iter().any(|capability: &String| capability == "AUTH" || capability.starts_with("AUTH ")),
+        "imap" =>
+            !upper.contains(&"LOGINDISABLED".to_string()) ||
+                upper.iter().any(|capability: &String| capability == "AUTH=PLAIN" || capability == "AUTH=LOGIN"),
+        _ =>
+            upper.iter().any(|capability: &String| {
+                capability == "USER" ||
+                    (capability.starts_with("SASL ") &&
+                        capability.split_whitespace().any(|mechanism: &str| mechanism == "PLAIN" || mechanism == "LOGIN"))
+            }),
+    }
+}
+
+fn offers_starttls(protocol: &str, capabilities: &[String]) -> bool {
+    let keyword: &str = if protocol == "pop3" { "STLS" } else { "STARTTLS" };
+
+    capabilities.iter().any(|capability: &String| capability.eq_ignore_ascii_case(keyword))
+}
+
+fn starttls<S: Read + Write>(protocol: &str, stream: &mut S) -> Result<()> {
+    let ok: bool = match protocol {
+        "smtp" => command(stream, "STARTTLS")?.code == 220,
+        "imap" =>
+            imap_command(stream, "a2", "STARTTLS")?
+                .last()
+                .map(|line: &String| line.starts_with("a2 OK"))
+                .unwrap_or(false),
+        _ => {
+            stream.write_all(b"STLS\r\n")?;
+            stream.flush()?;
+            read_line(stream)?.starts_with("+OK")
+        }
+    };
+
+    if !ok {
+        return Err(Error::msg("STARTTLS refused"));
+    }
+
+    Ok(())
+}
+
+fn quit<S: Read + Write>(protocol: &str, stream: &mut S) {
+    let line: &str = if protocol == "imap" { "a9 LOGOUT" } else { "QUIT" };
+    let _ = stream.write_all(format!("{}\r\n", line).as_bytes());
+}
+
+#[doc = "Connection ready for a TLS handshake: plain for implicit TLS, after STARTTLS otherwise"]
+fn tls_ready(endpoint: &MailEndpoint, ip: IpAddr, config: &SmtpConfig) -> Result<TcpStream> {
+    let mut stream: TcpStream = connect(ip, config)?;
+
+    if !endpoint.implicit_tls {
+        greeting(&endpoint.protocol, &mut stream)?;
+        capabilities(&endpoint.protocol, &mut stream, config)?;
+        starttls(&endpoint.protocol, &mut stream)?;
+    }
+
+    Ok(stream)
+}
+
+fn run_endpoint(endpoint: &mut MailEndpoint, ip: IpAddr, config: &SmtpConfig) -> Result<()> {
+    let protocol: String = endpoint.protocol.clone();
+    let host: String = endpoint.host.clone();
+
+    let mut stream: TcpStream = connect(ip, config)?;
+    endpoint.reachable = true;
+
+    let mut tls: SslStream<TcpStream> = if endpoint.implicit_tls {
+        let mut tls: SslStream<TcpStream> = handshake(stream, &host)?;
+        greeting(&protocol, &mut tls)?;
+        tls
+    } else {
+        greeting(&protocol, &mut stream)?;
+        let capabilities: Vec<String> = capabilities(&protocol, &mut stream, config)?;
+        endpoint.plaintext_auth_before_tls = plaintext_auth(&protocol, &capabilities);
+
+        if !offers_starttls(&protocol, &capabilities) {
+            quit(&protocol, &mut stream);
+            return Err(Error::msg("STARTTLS not offered"));
+        }
+
+        starttls(&protocol, &mut stream)?;
+        handshake(stream, &host)?
+    };
+
+    endpoint.tls_negotiated = true;
+    endpoint.tls_version = tls.ssl().version_str().to_string();
+
+    let chain: Vec<X509> = tls
+        .ssl()
+        .peer_cert_chain()
+        .map(|chain| chain.iter().map(|cert| cert.to_owned()).collect())
+        .unwrap_or_default();
+    let certificate: CertificateChain = check_chain(&ip.to_string(), &host, &chain);
+    endpoint.certificate_valid = certificate.trusted && certificate.hostname_match;
+    endpoint.certificate = Some(certificate);
+
+    quit(&protocol, &mut tls);
+
+    Ok(())
+}
+
+#[doc = "Connect to one endpoint and check its TLS and the AUTH it offers before TLS"]
+pub fn check_endpoint(endpoint: &mut MailEndpoint, ip: IpAddr, config: &SmtpConfig) {
+    endpoint.ip = ip.to_string();
+
+    if let Err(e) = run_endpoint(endpoint, ip, config) {
+        endpoint.error = e.to_string();
+    }
+}
+
+fn endpoint(service: (&str, &str, u16, bool), host: &str, port: u16, source: &str) -> MailEndpoint {
+    let (name, protocol, _port, implicit_tls) = service;
+
+    MailEndpoint {
+        service: name.to_string(),
+        protocol: protocol.to_string(),
+        host: host.trim_end_matches('.').to_string(),
+        port,
+        source: source.to_string(),
+        implicit_tls,
+        ip: String::new(),
+        reachable: false,
+        tls_negotiated: false,
+        tls_version: String::new(),
+        certificate: None,
+        certificate_valid: false,
+        key_exchange: None,
+        plaintext_auth_before_tls: false,
+        error: String::new(),
+    }
+}
+
+#[doc = "Endpoints from SRV records, or the conventional hostnames that resolve when a service has none"]
+fn discover(resolver: &Resolver, domain: &str, unavailable: &mut Vec<String>) -> Vec<MailEndpoint> {
+    let mut endpoints: Vec<MailEndpoint> = Vec::new();
+
+    for service in SERVICES {
+        let (name, protocol, port, _implicit_tls) = service;
+        let srv_name: String = format!("_{}._tcp.{}", name, domain);
+
+        let mut targets: Vec<(u16, u16, String)> = lookup_rdata(resolver, &srv_name, RecordType::SRV)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|rdata: &RData| {
+                match rdata {
+                    RData::SRV(srv) => Some((srv.priority(), srv.port(), srv.target().to_string())),
+                    _ => None,
+                }
+            })
+            .collect();
+        targets.sort();
+
+        // A target of "." says the service is deliberately not offered
+        if targets.iter().any(|(_priority, _port, target)| target == ".") {
+            unavailable.push(srv_name);
+            continue;
+        }
+
+        if !targets.is_empty() {
+            for (_priority, port, target) in targets {
+                endpoints.push(endpoint(service, &target, port, "srv"));
+            }
+            continue;
+        }
+
+        for label in conventional_hosts(protocol) {
+            let host: String = format!("{}.{}", label, domain);
+
+            if !addresses(resolver, &host, RecordType::A).unwrap_or_default().is_empty() {
+                endpoints.push(endpoint(service, &host, port, "hostname"));
+            }
+        }
+    }
+
+    endpoints
+}
+
+pub fn check_mail_clients(domain: &str) -> Result<MailClients> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let domain: &str = domain.trim_end_matches('.');
+    let defaults: SmtpConfig = SmtpConfig::default();
+
+    let mut unavailable: Vec<String> = Vec::new();
+    let mut endpoints: Vec<MailEndpoint> = discover(&resolver, domain, &mut unavailable);
+
+    for endpoint in endpoints.iter_mut() {
+        // A failed lookup for one host only affects its own endpoint
+        let ipv4: Result<Vec<IpAddr>> = addresses(&resolver, &endpoint.host, RecordType::A);
+        let ipv6: Result<Vec<IpAddr>> = addresses(&resolver, &endpoint.host, RecordType::AAAA);
+        let lookup_errors: Vec<String> = [&ipv4, &ipv6]
+            .iter()
+            .filter_map(|result: &&Result<Vec<IpAddr>>| result.as_ref().err())
+            .map(|e: &Error| e.to_string())
+            .collect();
+
+        let ip: Option<IpAddr> = ipv4
+            .unwrap_or_default()
+            .into_iter()
+            .chain(ipv6.unwrap_or_default())
+            .next();
+
+        match ip {
+            Some(ip) => {
+                let config: SmtpConfig = SmtpConfig { port: endpoint.port, ..defaults.clone() };
+                check_endpoint(endpoint, ip, &config);
+
+                if endpoint.tls_negotiated {
+                    let host: String = endpoint.host.clone();
+                    let key_exchange: KeyExchange = check_key_exchange(&host, &(|| tls_ready(endpoint, ip, &config)));
+                    endpoint.key_exchange = Some(key_exchange);
+                }
+            }
+            None if !lookup_errors.is_empty() => {
+                endpoint.error = format!("Address lookup for {} failed: {}", endpoint.host, lookup_errors.join("; "));
+            }
+            None => {
+                endpoint.error = format!("{} has no A or AAAA records", endpoint.host);
+            }
+        }
+    }
+
+    Ok(MailClients {
+        plaintext_auth_before_tls: endpoints.iter().any(|endpoint: &MailEndpoint| endpoint.plaintext_auth_before_tls),
+        endpoints,
+        unavailable,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ check_endpoint, endpoint, plaintext_auth, tls_ready, SERVICES };
+    use crate::smtp::SmtpConfig;
+    use crate::test_support::acceptor;
+    use crate::tls::handshake;
+    use crate::types::MailEndpoint;
+
+    use std::io::{ BufRead, BufReader, Read, Write };
+    use std::net::{ IpAddr, TcpListener, TcpStream };
+    use std::sync::mpsc::{ channel, Receiver, Sender };
+    use std::thread;
+
+    use openssl::ssl::{ SslAcceptor, SslStream };
+
+    fn exchange<S: Read + Write>(reader: &mut BufReader<S>, commands: &Sender<String>, reply: &str) -> bool {
+        let mut line: String = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return false;
+        }
+        let _ = commands.send(line.trim_end().to_string());
+        reader.get_mut().write_all(reply.as_bytes()).is_ok()
+    }
+
+    #[doc = "Greets, answers each plaintext command with the next reply, then takes the TLS handshake and reads the closing command"]
+    fn stand_in(
+        host: &'static str,
+        implicit_tls: bool,
+        greeting: &'static str,
+        replies: &'static [&'static str]
+    ) -> (u16, Receiver<String>) {
+        let acceptor: SslAcceptor = acceptor(host);
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port: u16 = listener.local_addr().unwrap().port();
+        let (commands, received): (Sender<String>, Receiver<String>) = channel();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            if !implicit_tls {
+                stream.write_all(greeting.as_bytes()).unwrap();
+
+                let mut reader: BufReader<TcpStream> = BufReader::new(stream);
+                for reply in replies {
+                    if !exchange(&mut reader, &commands, reply) {
+                        return;
+                    }
+                }
+                stream = reader.into_inner();
+            }
+
+            let mut tls: SslStream<TcpStream> = acceptor.accept(stream).unwrap();
+            if implicit_tls {
+                tls.write_all(greeting.as_bytes()).unwrap();
+            }
+
+            exchange(&mut BufReader::new(tls), &commands, "");
+        });
+
+        (port, received)
+    }
+
+    fn probe(service: usize, host: &str, port: u16) -> MailEndpoint {
+        let mut endpoint: MailEndpoint = endpoint(SERVICES[service], host, port, "srv");
+        let config: SmtpConfig = SmtpConfig { port, ..SmtpConfig::default() };
+
+        check_endpoint(&mut endpoint, "127.0.0.1".parse::<IpAddr>().unwrap(), &config);
+
+        endpoint
+    }
+
+    fn capabilities(list: &[&str]) -> Vec<String> {
+        list.iter().map(|capability: &&str| capability.to_string()).collect()
+    }
+
+    #[test]
+    fn detects_plaintext_auth() {
+        assert!(plaintext_auth("smtp", &capabilities(&["PIPELINING", "AUTH PLAIN LOGIN", "STARTTLS"])));
+        assert!(!plaintext_auth("smtp", &capabilities(&["PIPELINING", "STARTTLS"])));
+        assert!(plaintext_auth("imap", &capabilities(&["IMAP4rev1", "STARTTLS"])));
+        assert!(!plaintext_auth("imap", &capabilities(&["IMAP4rev1", "STARTTLS", "LOGINDISABLED"])));
+        assert!(plaintext_auth("pop3", &capabilities(&["TOP", "USER", "STLS"])));
+        assert!(!plaintext_auth("pop3", &capabilities(&["TOP", "SASL GSSAPI", "STLS"])));
+    }
+
+    #[test]
+    fn imap_starttls() {
+        let (port, commands) = stand_in(
+            "imap.example.test",
+            false,
+            "* OK IMAP4rev1 ready\r\n",
+            &["* CAPABILITY IMAP4rev1 STARTTLS LOGINDISABLED\r\na1 OK done\r\n", "a2 OK Begin TLS\r\n"]
+        );
+        let endpoint: MailEndpoint = probe(3, "imap.example.test", port);
+
+        assert!(endpoint.tls_negotiated, "{}", endpoint.error);
+        assert!(!endpoint.plaintext_auth_before_tls);
+        assert!(endpoint.certificate.is_some());
+        assert!(!endpoint.certificate_valid);
+        assert_eq!(commands.iter().collect::<Vec<String>>(), vec!["a1 CAPABILITY", "a2 STARTTLS", "a9 LOGOUT"]);
+    }
+
+    #[test]
+    fn pop3_starttls() {
+        let (port, commands) = stand_in(
+            "pop3.example.test",
+            false,
+            "+OK POP3 ready\r\n",
+            &["+OK\r\nTOP\r\nUSER\r\nSTLS\r\n.\r\n", "+OK Begin TLS\r\n"]
+        );
+        let endpoint: MailEndpoint = probe(5, "pop3.example.test", port);
+
+        assert!(endpoint.tls_negotiated, "{}", endpoint.error);
+        assert!(endpoint.plaintext_auth_before_tls);
+        assert_eq!(commands.iter().collect::<Vec<String>>(), vec!["CAPA", "STLS", "QUIT"]);
+    }
+
+    #[test]
+    fn starttls_not_offered() {
+        let (port, commands) = stand_in(
+            "imap.example.test",
+            false,
+            "* OK IMAP4rev1 ready\r\n",
+            &["* CAPABILITY IMAP4rev1 AUTH=PLAIN\r\na1 OK done\r\n", ""]
+        );
+        let endpoint: MailEndpoint = probe(3, "imap.example.test", port);
+
+        assert!(!endpoint.tls_negotiated);
+        assert!(endpoint.plaintext_auth_before_tls);
+        assert_eq!(endpoint.error, "STARTTLS not offered");
+        assert_eq!(commands.iter().collect::<Vec<String>>(), vec!["a1 CAPABILITY", "a9 LOGOUT"]);
+    }
+
+    #[test]
+    fn implicit_tls() {
+        for (service, host, greeting, closing) in [
+            (2, "imap.example.test", "* OK IMAP4rev1 ready\r\n", "a9 LOGOUT"),
+            (4, "pop3.example.test", "+OK POP3 ready\r\n", "QUIT"),
+        ] {
+            let (port, commands) = stand_in(host, true, greeting, &[]);
+            let endpoint: MailEndpoint = probe(service, host, port);
+
+            assert!(endpoint.tls_negotiated, "{}", endpoint.error);
+            assert!(!endpoint.plaintext_auth_before_tls);
+            assert!(!endpoint.tls_version.is_empty());
+            assert_eq!(commands.iter().collect::<Vec<String>>(), vec![closing]);
+        }
+    }
+
+    #[test]
+    fn reopens_starttls_for_key_exchange() {
+        let (port, commands) = stand_in(
+            "smtp.example.test",
+            false,
+            "220 smtp.example.test ESMTP\r\n",
+            &["250-smtp.example.test\r\n250 STARTTLS\r\n", "220 Go ahead\r\n"]
+        );
+        let endpoint: MailEndpoint = endpoint(SERVICES[1], "smtp.example.test", port, "srv");
+        let config: SmtpConfig = SmtpConfig { port, ..SmtpConfig::default() };
+
+        let stream = tls_ready(&endpoint, "127.0.0.1".parse::<IpAddr>().unwrap(), &config).unwrap();
+        let mut tls: SslStream<TcpStream> = handshake(stream, "smtp.example.test").unwrap();
+        tls.write_all(b"QUIT\r\n").unwrap();
+
+        assert_eq!(
+            commands.iter().collect::<Vec<String>>(),
+            vec![format!("EHLO {}", config.ehlo_name), "STARTTLS".to_string(), "QUIT".to_string()]
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+#[doc = "Snapshot of https://publicsuffix.org/list/public_suffix_list.dat, bundled so lookups need no network"]
+const PUBLIC_SUFFIX_LIST: &str = include_str!("public_suffix_list.dat");
+
+#[derive(Default)]
+struct Rules {
+    suffixes: HashSet<String>,
+    wildcards: HashSet<String>,
+    exceptions: HashSet<String>,
+}
+
+fn rules() -> &'static Rules {
+    static RULES: OnceLock<Rules> = OnceLock::new();
+
+    RULES.get_or_init(|| {
+        let mut rules: Rules = Rules::default();
+
+        for line in PUBLIC_SUFFIX_LIST.lines() {
+            let rule: &str = line.split_whitespace().next().unwrap_or("");
+
+            if rule.is_empty() || rule.starts_with("//") {
+                continue;
+            }
+
+            let rule: String = rule.to_lowercase();
+
+            if let Some(exception) = rule.strip_prefix('!') {
+                rules.exceptions.insert(exception.to_string());
+            } else if let Some(parent) = rule.strip_prefix("*.") {
+                rules.wildcards.insert(parent.to_string());
+            } else {
+                rules.suffixes.insert(rule);
+            }
+        }
+
+        rules
+    })
+}
+
+#[doc = "Number of trailing labels forming the public suffix; unlisted TLDs fall under the implicit * rule"]
+fn suffix_labels(labels: &[&str]) -> usize {
+    let rules: &Rules = rules();
+
+    // Longest matching rule wins, exceptions take precedence over wildcards
+    for start in 0..labels.len() {
+        let candidate: String = labels[start..].join(".");
+
+        if rules.exceptions.contains(&candidate) {
+            return labels.len() - start - 1;
+        }
+        if rules.suffixes.contains(&candidate) {
+            return labels.len() - start;
+        }
+        if start + 1 < labels.len() && rules.wildcards.contains(&labels[start + 1..].join(".")) {
+            return labels.len() - start;
+        }
+    }
+
+    1
+}
+
+#[doc = "RFC 7489 section 3.2 organizational domain: the public suffix plus one label"]
+pub fn organizational_domain(domain: &str) -> String {
+    let domain: String = domain.trim_end_matches('.').to_lowercase();
+    let labels: Vec<&str> = domain.split('.').collect();
+    let length: usize = (suffix_labels(&labels) + 1).min(labels.len());
+
+    labels[labels.len() - length..].join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::organizational_domain;
+
+    #[test]
+    fn finds_organizational_domain() {
+        assert_eq!(organizational_domain("mail.example.com."), "example.com");
+        assert_eq!(organizational_domain("example.com"), "example.com");
+        assert_eq!(organizational_domain("a.b.example.co.uk"), "example.co.uk");
+        assert_eq!(organizational_domain("foo.bar.unlisted-tld"), "bar.unlisted-tld");
+        assert_eq!(organizational_domain("com"), "com");
+        assert_eq!(organizational_domain("a.shop.example.ck"), "shop.example.ck");
+        assert_eq!(organizational_domain("a.www.ck"), "www.ck");
+    }
+}
@@ -0,0 +1,217 @@
+use std::io::{ Read, Write };
+use std::net::{ IpAddr, SocketAddr, TcpStream, UdpSocket };
+use std::prelude::v1::Result as stdResult;
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+
+use anyhow::{ Result, Error };
+use hickory_resolver::Resolver;
+use hickory_resolver::lookup::Lookup;
+use hickory_resolver::lookup_ip::LookupIp;
+use hickory_resolver::error::{ ResolveError, ResolveErrorKind };
+use hickory_resolver::proto::op::{ Edns, Message, MessageType, OpCode, Query, ResponseCode };
+use hickory_resolver::proto::rr::{ Name, RData, Record, RecordType };
+
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[doc = "Wire sizes and parsed response of a single DNS exchange"]
+pub struct Exchange {
+    pub request_size: usize,
+    pub response_size: usize,
+    pub response: Message,
+}
+
+pub fn build_query(name: &str, record_type: RecordType, dnssec_ok: bool) -> Result<Message> {
+    let mut message: Message = Message::new();
+    message
+        .set_id(rand_id())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(false)
+        .add_query(Query::query(Name::from_ascii(name)?, record_type));
+
+    let mut edns: Edns = Edns::new();
+    edns.set_max_payload(4096).set_version(0).set_dnssec_ok(dnssec_ok);
+    message.set_edns(edns);
+
+    Ok(message)
+}
+
+pub fn udp_socket(server: SocketAddr) -> Result<UdpSocket> {
+    let bind_addr: SocketAddr = match server.ip() {
+        IpAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+        IpAddr::V6(_) => SocketAddr::from(([0u16; 8], 0)),
+    };
+    let socket: UdpSocket = UdpSocket::bind(bind_addr)?;
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    socket.connect(server)?;
+
+    Ok(socket)
+}
+
+pub fn udp_exchange(server: SocketAddr, request: &Message) -> Result<Exchange> {
+    let socket: UdpSocket = udp_socket(server)?;
+
+    let bytes: Vec<u8> = request.to_vec()?;
+    socket.send(&bytes)?;
+
+    let mut buffer: [u8; 65535] = [0; 65535];
+    loop {
+        let size: usize = socket.recv(&mut buffer)?;
+        let response: Message = Message::from_vec(&buffer[..size])?;
+
+        // Ignore stray datagrams that do not answer this query
+        if response.id() == request.id() {
+            return Ok(Exchange {
+                request_size: bytes.len(),
+                response_size: size,
+                response,
+            });
+        }
+    }
+}
+
+pub fn tcp_exchange(server: IpAddr, request: &Message) -> Result<Message> {
+    let mut stream: TcpStream = TcpStream::connect_timeout(
+        &SocketAddr::new(server, 53),
+        QUERY_TIMEOUT
+    )?;
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+
+    let bytes: Vec<u8> = request.to_vec()?;
+    stream.write_all(&(bytes.len() as u16).to_be_bytes())?;
+    stream.write_all(&bytes)?;
+
+    let mut length: [u8; 2] = [0; 2];
+    stream.read_exact(&mut length)?;
+    let mut buffer: Vec<u8> = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut buffer)?;
+
+    Ok(Message::from_vec(&buffer)?)
+}
+
+pub fn query(server: IpAddr, name: &str, record_type: RecordType) -> Result<Message> {
+    let request: Message = build_query(name, record_type, true)?;
+    let exchange: Exchange = udp_exchange(SocketAddr::new(server, 53), &request)?;
+
+    if exchange.response.truncated() {
+        return tcp_exchange(server, &request);
+    }
+
+    Ok(exchange.response)
+}
+
+#[doc = "Host names of the nameservers delegated for a zone"]
+pub fn nameserver_names(resolver: &Resolver, zone: &str) -> Result<Vec<String>> {
+    let mut names: Vec<String> = Vec::new();
+
+    for record in resolver.lookup(zone, RecordType::NS)?.record_iter() {
+        let record_str: String = record.to_string();
+        let parts: Vec<&str> = record_str.split_whitespace().collect();
+        names.push(parts.get(4).unwrap_or(&"").to_string());
+    }
+
+    Ok(names)
+}
+
+pub fn nameserver_addresses(resolver: &Resolver, zone: &str) -> Result<Vec<IpAddr>> {
+    let mut addresses: Vec<IpAddr> = Vec::new();
+
+    for nsdomain in nameserver_names(resolver, zone)? {
+        let lookup: stdResult<LookupIp, ResolveError> = resolver.lookup_ip(nsdomain.as_str());
+        if let Ok(lookup) = lookup {
+            addresses.extend(lookup.iter());
+        }
+    }
+
+    if addresses.is_empty() {
+        return Err(Error::msg(format!("No nameserver addresses found for {}", zone)));
+    }
+
+    Ok(addresses)
+}
+
+#[doc = "RData of one type at a name; NXDOMAIN and no data are an empty list, not an error"]
+pub fn lookup_rdata(resolver: &Resolver, name: &str, record_type: RecordType) -> Result<Vec<RData>> {
+    let result: stdResult<Lookup, ResolveError> = resolver.lookup(name, record_type);
+
+    match result {
+        Ok(lookup) =>
+            Ok(
+                lookup
+                    .record_iter()
+                    .filter(|record: &&Record| record.record_type() == record_type)
+                    .filter_map(|record: &Record| record.data().cloned())
+                    .collect()
+            ),
+        Err(e) =>
+            match e.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => Ok(Vec::new()),
+                _ => Err(Error::new(e)),
+            }
+    }
+}
+
+#[doc = "TXT rdata with the character-strings of each record joined"]
+pub fn txt_strings(rdata: &[RData]) -> Vec<String> {
+    rdata
+        .iter()
+        .filter_map(|rdata: &RData| {
+            match rdata {
+                RData::TXT(txt) =>
+                    Some(
+                        txt
+                            .txt_data()
+                            .iter()
+                            .map(|part| String::from_utf8_lossy(part).to_string())
+                            .collect::<String>()
+                    ),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[doc = "TXT records of a name with their character-strings joined"]
+pub fn txt_records(resolver: &Resolver, name: &str) -> Result<Vec<String>> {
+    Ok(txt_strings(&lookup_rdata(resolver, name, RecordType::TXT)?))
+}
+
+fn rand_id() -> u16 {
+    let nanos: u32 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+
+    (nanos ^ (nanos >> 16)) as u16
+}
+
+pub fn ip_addresses(rdata: &[RData]) -> Vec<IpAddr> {
+    rdata
+        .iter()
+        .filter_map(|rdata: &RData| {
+            match rdata {
+                RData::A(a) => Some(IpAddr::V4(a.0)),
+                RData::AAAA(aaaa) => Some(IpAddr::V6(aaaa.0)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+pub fn addresses(resolver: &Resolver, name: &str, record_type: RecordType) -> Result<Vec<IpAddr>> {
+    Ok(ip_addresses(&lookup_rdata(resolver, name, record_type)?))
+}
+
+#[doc = "False only on NXDOMAIN; a name with other record types but no address still exists"]
+pub fn name_exists(resolver: &Resolver, name: &str) -> Result<bool> {
+    let result: stdResult<Lookup, ResolveError> = resolver.lookup(name, RecordType::A);
+
+    match result {
+        Ok(_lookup) => Ok(true),
+        Err(e) =>
+            match e.kind() {
+                ResolveErrorKind::NoRecordsFound { response_code, .. } => Ok(*response_code != ResponseCode::NXDomain),
+                _ => Err(Error::new(e)),
+            }
+    }
+}
@@ -0,0 +1,269 @@
+use crate::types::ReverseDns;
+
+use std::net::IpAddr;
+use std::prelude::v1::Result as stdResult;
+
+use crate::query::{ addresses, lookup_rdata };
+
+use anyhow::Result;
+use hickory_resolver::Resolver;
+use hickory_resolver::lookup::Lookup;
+use hickory_resolver::error::ResolveError;
+use hickory_resolver::proto::rr::{ RData, RecordType };
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+
+pub fn ptr_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ipv4) => {
+            let octets: [u8; 4] = ipv4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", octets[3], octets[2], octets[1], octets[0])
+        }
+        IpAddr::V6(ipv6) => {
+            let nibbles: Vec<String> = ipv6
+                .segments()
+                .iter()
+                .rev()
+                .flat_map(|segment: &u16| {
+                    (0..4).map(move |i: u16| format!("{:x}", (segment >> (i * 4)) & 0xf))
+                })
+                .collect();
+
+            format!("{}.ip6.arpa", nibbles.join("."))
+        }
+    }
+}
+
+fn ptr_names(resolver: &Resolver, ip: IpAddr) -> Result<Vec<String>> {
+    Ok(
+        lookup_rdata(resolver, &ptr_name(ip), RecordType::PTR)?
+            .iter()
+            .filter_map(|rdata: &RData| {
+                match rdata {
+                    RData::PTR(name) => Some(name.to_string()),
+                    _ => None,
+                }
+            })
+            .collect()
+    )
+}
+
+#[doc = "First PTR name for an address, empty when none exists"]
+pub fn ptr_record(resolver: &Resolver, ip: IpAddr) -> Result<String> {
+    Ok(ptr_names(resolver, ip)?.into_iter().next().unwrap_or_default())
+}
+
+pub fn host_addresses(resolver: &Resolver, host: &str) -> Vec<IpAddr> {
+    let mut addresses: Vec<IpAddr> = Vec::new();
+
+    for record_type in [RecordType::A, RecordType::AAAA] {
+        let result: stdResult<Lookup, ResolveError> = resolver.lookup(host, record_type);
+        if let Ok(lookup) = result {
+            addresses.extend(lookup.iter().filter_map(|rdata: &RData| rdata.ip_addr()));
+        }
+    }
+
+    addresses
+}
+
+fn forward_addresses(resolver: &Resolver, host: &str) -> Result<Vec<IpAddr>> {
+    let mut found: Vec<IpAddr> = addresses(resolver, host, RecordType::A)?;
+    found.extend(addresses(resolver, host, RecordType::AAAA)?);
+
+    Ok(found)
+}
+
+fn reverse_dns(ip: IpAddr) -> ReverseDns {
+    ReverseDns {
+        sources: Vec::new(),
+        hosts: Vec::new(),
+        ip: ip.to_string(),
+        ptr: String::new(),
+        forward_addresses: Vec::new(),
+        confirmed: false,
+        error: String::new(),
+    }
+}
+
+#[doc = "One entry per address, listing every source and host it was found under"]
+fn by_address(found: &[(&str, String, IpAddr)]) -> Vec<(IpAddr, ReverseDns)> {
+    let mut entries: Vec<(IpAddr, ReverseDns)> = Vec::new();
+
+    for (source, host, ip) in found {
+        let index: usize = match entries.iter().position(|(known, _)| known == ip) {
+            Some(index) => index,
+            None => {
+                entries.push((*ip, reverse_dns(*ip)));
+                entries.len() - 1
+            }
+        };
+
+        let reverse: &mut ReverseDns = &mut entries[index].1;
+        let host: String = host.trim_end_matches('.').to_string();
+
+        if !reverse.sources.iter().any(|known: &String| known == source) {
+            reverse.sources.push(source.to_string());
+        }
+        if !reverse.hosts.contains(&host) {
+            reverse.hosts.push(host);
+        }
+    }
+
+    entries
+}
+
+#[doc = "Forward-confirmed reverse DNS: every PTR name is resolved again and must point back at the address"]
+pub fn reverse_lookup(resolver: &Resolver, reverse: &mut ReverseDns, ip: IpAddr) {
+    let ptrs: Vec<String> = match ptr_names(resolver, ip) {
+        Ok(ptrs) => ptrs,
+        Err(e) => {
+            reverse.error = format!("PTR lookup failed: {}", e);
+            return;
+        }
+    };
+
+    for ptr in ptrs {
+        let forward: Vec<IpAddr> = match forward_addresses(resolver, &ptr) {
+            Ok(forward) => forward,
+            Err(e) => {
+                reverse.error = format!("Address lookup for {} failed: {}", ptr, e);
+                Vec::new()
+            }
+        };
+        let confirmed: bool = forward.contains(&ip);
+
+        if reverse.ptr.is_empty() || confirmed {
+            reverse.ptr = ptr;
+            reverse.forward_addresses = forward
+                .iter()
+                .map(|address: &IpAddr| address.to_string())
+                .collect();
+            reverse.confirmed = confirmed;
+        }
+
+        if confirmed {
+            reverse.error = String::new();
+            break;
+        }
+    }
+}
+
+pub fn check_reverse_dns(domain: &str) -> Result<Vec<ReverseDns>> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let mut hosts: Vec<(&str, String)> = Vec::new();
+
+    let ns_result: stdResult<Lookup, ResolveError> = resolver.lookup(domain, RecordType::NS);
+    if let Ok(lookup) = ns_result {
+        for rdata in lookup.iter() {
+            if let RData::NS(name) = rdata {
+                hosts.push(("ns", name.to_string()));
+            }
+        }
+    }
+
+    let mx_result: stdResult<Lookup, ResolveError> = resolver.lookup(domain, RecordType::MX);
+    if let Ok(lookup) = mx_result {
+        for rdata in lookup.iter() {
+            if let RData::MX(mx) = rdata {
+                hosts.push(("mx", mx.exchange().to_string()));
+            }
+        }
+    }
+
+    hosts.push(("apex", domain.to_string()));
+    hosts.push(("www", format!("www.{}", domain)));
+
+    let found: Vec<(&str, String, IpAddr)> = hosts
+        .into_iter()
+        .flat_map(|(source, host)| {
+            host_addresses(&resolver, &host)
+                .into_iter()
+                .map(move |ip: IpAddr| (source, host.clone(), ip))
+        })
+        .collect();
+
+    let mut results: Vec<ReverseDns> = Vec::new();
+
+    for (ip, mut reverse) in by_address(&found) {
+        reverse_lookup(&resolver, &mut reverse, ip);
+        results.push(reverse);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ by_address, ptr_name };
+    use crate::types::ReverseDns;
+
+    use std::net::IpAddr;
+
+    fn ptr(address: &str) -> String {
+        ptr_name(address.parse::<IpAddr>().unwrap())
+    }
+
+    #[test]
+    fn ipv4_ptr_name() {
+        assert_eq!(ptr("192.0.2.10"), "10.2.0.192.in-addr.arpa");
+    }
+
+    #[test]
+    fn compressed_ipv6_ptr_name() {
+        assert_eq!(
+            ptr("2001:db8::1"),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+        assert_eq!(
+            ptr("::1"),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa"
+        );
+    }
+
+    #[test]
+    fn embedded_ipv4_ptr_name() {
+        assert_eq!(
+            ptr("::ffff:192.0.2.1"),
+            "1.0.2.0.0.0.0.c.f.f.f.f.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.ip6.arpa"
+        );
+        assert_eq!(
+            ptr("64:ff9b::192.0.2.33"),
+            "1.2.2.0.0.0.0.c.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.b.9.f.f.4.6.0.0.ip6.arpa"
+        );
+    }
+
+    #[test]
+    fn full_form_ipv6_ptr_name() {
+        assert_eq!(
+            ptr("2001:0db8:0000:0000:0000:ff00:0042:8329"),
+            "9.2.3.8.2.4.0.0.0.0.f.f.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
+
+    #[test]
+    fn one_entry_per_address() {
+        let shared: IpAddr = "192.0.2.1".parse::<IpAddr>().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse::<IpAddr>().unwrap();
+        let mx: IpAddr = "192.0.2.25".parse::<IpAddr>().unwrap();
+
+        let entries: Vec<(IpAddr, ReverseDns)> = by_address(
+            &[
+                ("ns", "ns1.example.test.".to_string(), shared),
+                ("mx", "mx.example.test.".to_string(), mx),
+                ("apex", "example.test".to_string(), shared),
+                ("apex", "example.test".to_string(), v6),
+                ("www", "www.example.test".to_string(), shared),
+                ("www", "www.example.test".to_string(), v6),
+            ]
+        );
+
+        let ips: Vec<IpAddr> = entries.iter().map(|(ip, _)| *ip).collect();
+        assert_eq!(ips, vec![shared, mx, v6]);
+
+        assert_eq!(entries[0].1.ip, "192.0.2.1");
+        assert_eq!(entries[0].1.sources, vec!["ns", "apex", "www"]);
+        assert_eq!(entries[0].1.hosts, vec!["ns1.example.test", "example.test", "www.example.test"]);
+        assert_eq!(entries[1].1.sources, vec!["mx"]);
+        assert_eq!(entries[2].1.sources, vec!["apex", "www"]);
+        assert!(entries.iter().all(|(_, reverse)| reverse.ptr.is_empty() && reverse.error.is_empty()));
+    }
+}
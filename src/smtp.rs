@@ -0,0 +1,407 @@
+use crate::types::{ RelayTest, SmtpProbe };
+use crate::tls::handshake;
+
+use std::env::var;
+use std::io::{ Read, Write };
+use std::net::{ IpAddr, SocketAddr, TcpStream };
+use std::time::Duration;
+
+use anyhow::{ Result, Error };
+use openssl::ssl::SslStream;
+use openssl::x509::X509;
+
+#[doc = "SMTP probe settings, overridable with SMTP_EHLO_NAME, SMTP_CONNECT_TIMEOUT, SMTP_READ_TIMEOUT (seconds), SMTP_RELAY_SENDER and SMTP_RELAY_RECIPIENT"]
+#[derive(Clone)]
+pub struct SmtpConfig {
+    pub port: u16,
+    pub ehlo_name: String,
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub relay_sender: String,
+    pub relay_recipient: String,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        let seconds = |key: &str, default: u64| {
+            Duration::from_secs(
+                var(key)
+                    .ok()
+                    .and_then(|value: String| value.trim().parse().ok())
+                    .unwrap_or(default)
+            )
+        };
+
+        SmtpConfig {
+            port: 25,
+            ehlo_name: var("SMTP_EHLO_NAME").unwrap_or("bright".to_string()),
+            connect_timeout: seconds("SMTP_CONNECT_TIMEOUT", 5),
+            read_timeout: seconds("SMTP_READ_TIMEOUT", 20),
+            relay_sender: var("SMTP_RELAY_SENDER").unwrap_or("relay-test@example.com".to_string()),
+            relay_recipient: var("SMTP_RELAY_RECIPIENT").unwrap_or("relay-test@example.net".to_string()),
+        }
+    }
+}
+
+#[doc = "Multi-line SMTP reply: status code and the text of every line"]
+pub struct SmtpReply {
+    pub code: u16,
+    pub lines: Vec<String>,
+}
+
+pub fn read_line<S: Read>(stream: &mut S) -> Result<String> {
+    let mut line: Vec<u8> = Vec::new();
+    let mut byte: [u8; 1] = [0; 1];
+
+    // Byte at a time so nothing past the reply is consumed before a TLS handshake
+    while !line.ends_with(b"\r\n") {
+        if stream.read(&mut byte)? == 0 {
+            return Err(Error::msg("Connection closed by server"));
+        }
+        line.push(byte[0]);
+
+        if line.len() > 4096 {
+            return Err(Error::msg("SMTP reply line too long"));
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+pub fn read_reply<S: Read>(stream: &mut S) -> Result<SmtpReply> {
+    let mut lines: Vec<String> = Vec::new();
+
+    loop {
+        let line: String = read_line(stream)?;
+        let code: u16 = line
+            .get(..3)
+            .and_then(|code: &str| code.parse().ok())
+            .ok_or_else(|| Error::msg(format!("Malformed SMTP reply: {}", line)))?;
+        let last: bool = line.as_bytes().get(3) != Some(&b'-');
+
+        lines.push(line.get(4..).unwrap_or("").to_string());
+
+        if last {
+            return Ok(SmtpReply { code, lines });
+        }
+    }
+}
+
+pub fn command<S: Read + Write>(stream: &mut S, line: &str) -> Result<SmtpReply> {
+    stream.write_all(format!("{}\r\n", line).as_bytes())?;
+    stream.flush()?;
+
+    read_reply(stream)
+}
+
+pub fn connect(ip: IpAddr, config: &SmtpConfig) -> Result<TcpStream> {
+    let stream: TcpStream = TcpStream::connect_timeout(&SocketAddr::new(ip, config.port), config.connect_timeout)?;
+    stream.set_read_timeout(Some(config.read_timeout))?;
+    stream.set_write_timeout(Some(config.read_timeout))?;
+
+    Ok(stream)
+}
+
+#[doc = "Connect, read the banner, EHLO and issue STARTTLS, leaving the socket ready for a TLS handshake"]
+pub fn starttls(ip: IpAddr, config: &SmtpConfig) -> Result<TcpStream> {
+    let mut stream: TcpStream = connect(ip, config)?;
+
+    let banner: SmtpReply = read_reply(&mut stream)?;
+    if banner.code != 220 {
+        return Err(Error::msg(format!("Unexpected SMTP banner {}", banner.code)));
+    }
+
+    let ehlo: SmtpReply = command(&mut stream, &format!("EHLO {}", config.ehlo_name))?;
+    if ehlo.code != 250 {
+        return Err(Error::msg(format!("EHLO rejected with {}", ehlo.code)));
+    }
+
+    let reply: SmtpReply = command(&mut stream, "STARTTLS")?;
+    if reply.code != 220 {
+        return Err(Error::msg(format!("STARTTLS rejected with {}", reply.code)));
+    }
+
+    Ok(stream)
+}
+
+#[doc = "Probe one MX address; also returns the certificate chain presented after STARTTLS, leaf first"]
+pub fn probe_smtp(ip: IpAddr, mx_name: &str, config: &SmtpConfig) -> (SmtpProbe, Vec<X509>) {
+    let mut probe: SmtpProbe = SmtpProbe {
+        ip: ip.to_string(),
+        port: config.port,
+        reachable: false,
+        banner: String::new(),
+        greeting_hostname: String::new(),
+        ehlo_extensions: Vec::new(),
+        starttls_advertised: false,
+        starttls_negotiated: false,
+        tls_version: String::new(),
+        tls_cipher: String::new(),
+        error: String::new(),
+    };
+    let mut chain: Vec<X509> = Vec::new();
+
+    if let Err(e) = run_probe(ip, mx_name, config, &mut probe, &mut chain) {
+        probe.error = e.to_string();
+    }
+
+    (probe, chain)
+}
+
+fn run_probe(
+    ip: IpAddr,
+    mx_name: &str,
+    config: &SmtpConfig,
+    probe: &mut SmtpProbe,
+    chain: &mut Vec<X509>
+) -> Result<()> {
+    let mut stream: TcpStream = connect(ip, config)?;
+    probe.reachable = true;
+
+    let banner: SmtpReply = read_reply(&mut stream)?;
+    probe.banner = banner.lines.join(" ");
+    probe.greeting_hostname = banner.lines
+        .first()
+        .and_then(|line: &String| line.split_whitespace().next())
+        .unwrap_or("")
+        .to_string();
+
+    if banner.code != 220 {
+        return Err(Error::msg(format!("Unexpected SMTP banner {}", banner.code)));
+    }
+
+    let ehlo: SmtpReply = command(&mut stream, &format!("EHLO {}", config.ehlo_name))?;
+    if ehlo.code != 250 {
+        return Err(Error::msg(format!("EHLO rejected with {}", ehlo.code)));
+    }
+
+    // First EHLO line is the server greeting, the rest are extensions
+    probe.ehlo_extensions = ehlo.lines.iter().skip(1).cloned().collect();
+    probe.starttls_advertised = probe.ehlo_extensions
+        .iter()
+        .any(|extension: &String| extension.eq_ignore_ascii_case("STARTTLS"));
+
+    if !probe.starttls_advertised {
+        let _ = command(&mut stream, "QUIT");
+        return Ok(());
+    }
+
+    let reply: SmtpReply = command(&mut stream, "STARTTLS")?;
+    if reply.code != 220 {
+        let _ = command(&mut stream, "QUIT");
+        return Err(Error::msg(format!("STARTTLS rejected with {}", reply.code)));
+    }
+
+    let mut tls: SslStream<TcpStream> = handshake(stream, mx_name)?;
+    probe.starttls_negotiated = true;
+    probe.tls_version = tls.ssl().version_str().to_string();
+    probe.tls_cipher = tls
+        .ssl()
+        .current_cipher()
+        .map(|cipher| cipher.name().to_string())
+        .unwrap_or_default();
+
+    if let Some(peer_chain) = tls.ssl().peer_cert_chain() {
+        chain.extend(peer_chain.iter().map(|cert| cert.to_owned()));
+    }
+
+    let _ = command(&mut tls, "QUIT");
+
+    Ok(())
+}
+
+fn reply_text(reply: &SmtpReply) -> String {
+    format!("{} {}", reply.code, reply.lines.join(" ")).trim_end().to_string()
+}
+
+#[doc = "MAIL FROM and RCPT TO for third party addresses; the transaction is reset, DATA is never sent"]
+fn relay_transaction<S: Read + Write>(stream: &mut S, config: &SmtpConfig, test: &mut RelayTest) -> Result<()> {
+    let mail: SmtpReply = command(stream, &format!("MAIL FROM:<{}>", config.relay_sender))?;
+    test.mail_from_reply = reply_text(&mail);
+
+    if mail.code == 250 {
+        let rcpt: SmtpReply = command(stream, &format!("RCPT TO:<{}>", config.relay_recipient))?;
+        test.rcpt_to_reply = reply_text(&rcpt);
+        test.open_relay = rcpt.code == 250 || rcpt.code == 251;
+
+        let _ = command(stream, "RSET");
+    }
+
+    let _ = command(stream, "QUIT");
+
+    Ok(())
+}
+
+fn run_relay_test(ip: IpAddr, mx_name: &str, config: &SmtpConfig, test: &mut RelayTest) -> Result<()> {
+    let mut stream: TcpStream = connect(ip, config)?;
+
+    let banner: SmtpReply = read_reply(&mut stream)?;
+    if banner.code != 220 {
+        return Err(Error::msg(format!("Unexpected SMTP banner {}", banner.code)));
+    }
+
+    let ehlo: SmtpReply = command(&mut stream, &format!("EHLO {}", config.ehlo_name))?;
+    if ehlo.code != 250 {
+        return Err(Error::msg(format!("EHLO rejected with {}", ehlo.code)));
+    }
+
+    let starttls: bool = ehlo.lines
+        .iter()
+        .skip(1)
+        .any(|extension: &String| extension.eq_ignore_ascii_case("STARTTLS"));
+
+    // Servers may only accept mail over TLS, so relay through the same path a sender would use
+    if starttls && command(&mut stream, "STARTTLS")?.code == 220 {
+        let mut tls: SslStream<TcpStream> = handshake(stream, mx_name)?;
+        test.starttls = true;
+
+        let ehlo: SmtpReply = command(&mut tls, &format!("EHLO {}", config.ehlo_name))?;
+        if ehlo.code != 250 {
+            return Err(Error::msg(format!("EHLO rejected with {}", ehlo.code)));
+        }
+
+        return relay_transaction(&mut tls, config, test);
+    }
+
+    relay_transaction(&mut stream, config, test)
+}
+
+#[doc = "Whether the server accepts a recipient outside its domains from a sender outside its domains"]
+pub fn relay_test(ip: IpAddr, mx_name: &str, config: &SmtpConfig) -> RelayTest {
+    let mut test: RelayTest = RelayTest {
+        ip: ip.to_string(),
+        sender: config.relay_sender.clone(),
+        recipient: config.relay_recipient.clone(),
+        starttls: false,
+        mail_from_reply: String::new(),
+        rcpt_to_reply: String::new(),
+        open_relay: false,
+        error: String::new(),
+    };
+
+    if let Err(e) = run_relay_test(ip, mx_name, config, &mut test) {
+        test.error = e.to_string();
+    }
+
+    test
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ probe_smtp, relay_test, SmtpConfig };
+
+    use std::io::{ BufRead, BufReader, Write };
+    use std::net::{ IpAddr, TcpListener, TcpStream };
+    use std::sync::mpsc::{ channel, Receiver, Sender };
+    use std::thread;
+    use std::time::Duration;
+
+    #[doc = "Answers each command with the next reply and passes the commands it received back to the test"]
+    fn stand_in(replies: &'static [&'static str]) -> (u16, Receiver<String>) {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port: u16 = listener.local_addr().unwrap().port();
+        let (commands, received): (Sender<String>, Receiver<String>) = channel();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer: TcpStream = stream.try_clone().unwrap();
+            let mut reader: BufReader<TcpStream> = BufReader::new(stream);
+
+            writer.write_all(b"220 mx.example.test ESMTP stand-in\r\n").unwrap();
+
+            for reply in replies {
+                let mut line: String = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let _ = commands.send(line.trim_end().to_string());
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+
+        (port, received)
+    }
+
+    fn config(port: u16) -> SmtpConfig {
+        SmtpConfig {
+            port,
+            ehlo_name: "bright.test".to_string(),
+            connect_timeout: Duration::from_secs(2),
+            read_timeout: Duration::from_secs(2),
+            relay_sender: "relay-test@example.com".to_string(),
+            relay_recipient: "relay-test@example.net".to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_banner_and_extensions() {
+        let (port, _commands) = stand_in(
+            &["250-mx.example.test\r\n250-PIPELINING\r\n250-SIZE 10240000\r\n250 8BITMIME\r\n", "221 Bye\r\n"]
+        );
+        let (probe, _chain) = probe_smtp("127.0.0.1".parse::<IpAddr>().unwrap(), "mx.example.test", &config(port));
+
+        assert!(probe.reachable);
+        assert_eq!(probe.greeting_hostname, "mx.example.test");
+        assert_eq!(probe.ehlo_extensions, vec!["PIPELINING", "SIZE 10240000", "8BITMIME"]);
+        assert!(!probe.starttls_advertised);
+        assert!(!probe.starttls_negotiated);
+        assert!(probe.error.is_empty());
+    }
+
+    #[test]
+    fn reports_rejected_starttls() {
+        let (port, _commands) = stand_in(
+            &["250-mx.example.test\r\n250 STARTTLS\r\n", "454 TLS not available\r\n", "221 Bye\r\n"]
+        );
+        let (probe, _chain) = probe_smtp("127.0.0.1".parse::<IpAddr>().unwrap(), "mx.example.test", &config(port));
+
+        assert!(probe.starttls_advertised);
+        assert!(!probe.starttls_negotiated);
+        assert!(probe.error.contains("454"));
+    }
+
+    #[test]
+    fn unreachable_server() {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port: u16 = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let (probe, _chain) = probe_smtp("127.0.0.1".parse::<IpAddr>().unwrap(), "mx.example.test", &config(port));
+
+        assert!(!probe.reachable);
+        assert!(!probe.error.is_empty());
+    }
+
+    #[test]
+    fn detects_open_relay_without_sending_data() {
+        let (port, commands) = stand_in(
+            &["250 mx.example.test\r\n", "250 OK\r\n", "250 Accepted\r\n", "250 Reset\r\n", "221 Bye\r\n"]
+        );
+        let test = relay_test("127.0.0.1".parse::<IpAddr>().unwrap(), "mx.example.test", &config(port));
+
+        assert!(test.open_relay, "{}", test.error);
+        assert_eq!(test.rcpt_to_reply, "250 Accepted");
+        assert_eq!(
+            commands.iter().collect::<Vec<String>>(),
+            vec![
+                "EHLO bright.test",
+                "MAIL FROM:<relay-test@example.com>",
+                "RCPT TO:<relay-test@example.net>",
+                "RSET",
+                "QUIT"
+            ]
+        );
+    }
+
+    #[test]
+    fn refused_relay() {
+        let (port, _commands) = stand_in(
+            &["250 mx.example.test\r\n", "250 OK\r\n", "554 5.7.1 Relay access denied\r\n", "250 Reset\r\n", "221 Bye\r\n"]
+        );
+        let test = relay_test("127.0.0.1".parse::<IpAddr>().unwrap(), "mx.example.test", &config(port));
+
+        assert!(!test.open_relay);
+        assert!(test.error.is_empty());
+        assert_eq!(test.rcpt_to_reply, "554 5.7.1 Relay access denied");
+    }
+}
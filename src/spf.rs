@@ -0,0 +1,1361 @@
+use crate::types::{ Spf, SpfEvaluation, SpfInclude };
+use crate::reverse::ptr_name;
+use crate::query::{ addresses, ip_addresses, lookup_rdata, txt_records, txt_strings };
+
+use std::fmt;
+use std::net::IpAddr;
+use std::prelude::v1::Result as stdResult;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use anyhow::{ Result, Error };
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+use hickory_resolver::proto::rr::{ RData, RecordType };
+
+pub const LOOKUP_LIMIT: u32 = 10;
+pub const VOID_LOOKUP_LIMIT: u32 = 2;
+const MECHANISMS: [&str; 8] = ["all", "include", "a", "mx", "ptr", "ip4", "ip6", "exists"];
+
+#[doc = "One SPF directive: qualifier, mechanism name, its domain-spec or address and the dual CIDR lengths"]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive {
+    pub qualifier: char,
+    pub mechanism: String,
+    pub value: String,
+    pub cidr4: Option<u8>,
+    pub cidr6: Option<u8>,
+}
+
+impl fmt::Display for Directive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text: String = String::new();
+
+        if self.qualifier != '+' {
+            text.push(self.qualifier);
+        }
+        text.push_str(&self.mechanism);
+        if !self.value.is_empty() {
+            text.push(':');
+            text.push_str(&self.value);
+        }
+        if let Some(cidr4) = self.cidr4 {
+            text.push_str(&format!("/{}", cidr4));
+        }
+        if let Some(cidr6) = self.cidr6 {
+            text.push_str(&format!("//{}", cidr6));
+        }
+
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SpfRecord {
+    pub directives: Vec<Directive>,
+    pub redirect: Option<String>,
+    pub exp: Option<String>,
+}
+
+pub fn is_spf(record: &str) -> bool {
+    let lower: String = record.to_lowercase();
+    lower == "v=spf1" || lower.starts_with("v=spf1 ")
+}
+
+#[doc = "RFC 7208 section 7.1 macro-string: literals plus %{letter digits r delimiters}, %%, %_ and %-"]
+pub fn valid_macro_string(value: &str, exp: bool) -> bool {
+    let letters: &str = if exp { "slodiphcrtv" } else { "slodiphv" };
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            if !('!'..='~').contains(&c) {
+                return false;
+            }
+            continue;
+        }
+
+        match chars.next() {
+            Some('%') | Some('_') | Some('-') => {}
+            Some('{') => {
+                match chars.next() {
+                    Some(letter) if letters.contains(letter.to_ascii_lowercase()) => {}
+                    _ => {
+                        return false;
+                    }
+                }
+                while chars.peek().map(|c: &char| c.is_ascii_digit()).unwrap_or(false) {
+                    chars.next();
+                }
+                if chars.peek().map(|c: &char| c.eq_ignore_ascii_case(&'r')).unwrap_or(false) {
+                    chars.next();
+                }
+                loop {
+                    match chars.next() {
+                        Some('}') => {
+                            break;
+                        }
+                        Some(delimiter) if ".-+,/_=".contains(delimiter) => {}
+                        _ => {
+                            return false;
+                        }
+                    }
+                }
+            }
+            _ => {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn valid_domain_spec(value: &str) -> bool {
+    if value.is_empty() || !valid_macro_string(value, false) {
+        return false;
+    }
+
+    // Without macros the last label must be a plausible top-level domain
+    value.contains('%') ||
+        value
+            .trim_end_matches('.')
+            .rsplit('.')
+            .next()
+            .map(|tld: &str| !tld.is_empty() && !tld.chars().all(|c: char| c.is_ascii_digit()))
+            .unwrap_or(false)
+}
+
+fn parse_cidr(text: &str, max: u8) -> Result<u8, String> {
+    match text.parse::<u8>() {
+        Ok(length) if length <= max && !(text.len() > 1 && text.starts_with('0')) => Ok(length),
+        _ => Err(format!("Invalid CIDR length /{}", text)),
+    }
+}
+
+#[doc = "Split a trailing /cidr4//cidr6 off a domain-spec, ignoring slashes inside macros"]
+fn split_cidr(text: &str) -> Result<(String, Option<u8>, Option<u8>), String> {
+    let mut depth: i32 = 0;
+    let mut start: Option<usize> = None;
+
+    for (index, c) in text.char_indices() {
+        match c {
+            '{' => {
+                depth += 1;
+            }
+            '}' => {
+                depth -= 1;
+            }
+            '/' if depth == 0 => {
+                start = Some(index);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let start: usize = match start {
+        Some(start) => start,
+        None => {
+            return Ok((text.to_string(), None, None));
+        }
+    };
+
+    let cidrs: &str = &text[start..];
+    let (cidr4, cidr6): (Option<&str>, Option<&str>) = match cidrs.strip_prefix("//") {
+        Some(cidr6) => (None, Some(cidr6)),
+        None =>
+            match cidrs[1..].split_once("//") {
+                Some((cidr4, cidr6)) => (Some(cidr4), Some(cidr6)),
+                None => (Some(&cidrs[1..]), None),
+            }
+    };
+
+    Ok((
+        text[..start].to_string(),
+        cidr4.map(|cidr: &str| parse_cidr(cidr, 32)).transpose()?,
+        cidr6.map(|cidr: &str| parse_cidr(cidr, 128)).transpose()?,
+    ))
+}
+
+fn parse_directive(term: &str) -> Result<Directive, String> {
+    let (qualifier, rest): (char, &str) = match term.chars().next() {
+        Some(c) if "+-~?".contains(c) => (c, &term[1..]),
+        _ => ('+', term),
+    };
+
+    let name_end: usize = rest.find([':', '/']).unwrap_or(rest.len());
+    let mechanism: String = rest[..name_end].to_lowercase();
+    let argument: &str = &rest[name_end..];
+
+    if !MECHANISMS.contains(&mechanism.as_str()) {
+        return Err(format!("Unknown mechanism {}", term));
+    }
+
+    let mut directive: Directive = Directive {
+        qualifier,
+        mechanism: mechanism.clone(),
+        value: String::new(),
+        cidr4: None,
+        cidr6: None,
+    };
+
+    match mechanism.as_str() {
+        "all" => {
+            if !argument.is_empty() {
+                return Err(format!("all takes no argument: {}", term));
+            }
+        }
+        "include" | "exists" | "ptr" => {
+            let value: &str = argument.strip_prefix(':').unwrap_or("");
+
+            if mechanism != "ptr" && value.is_empty() {
+                return Err(format!("{} needs a domain: {}", mechanism, term));
+            }
+            if !value.is_empty() && !valid_domain_spec(value) {
+                return Err(format!("Invalid domain-spec in {}", term));
+            }
+            if argument.starts_with('/') {
+                return Err(format!("{} takes no CIDR length: {}", mechanism, term));
+            }
+            directive.value = value.to_string();
+        }
+        "a" | "mx" => {
+            let (value, cidr4, cidr6) = split_cidr(argument.strip_prefix(':').unwrap_or(argument))?;
+
+            if argument.starts_with(':') && !valid_domain_spec(&value) {
+                return Err(format!("Invalid domain-spec in {}", term));
+            }
+            directive.value = value;
+            directive.cidr4 = cidr4;
+            directive.cidr6 = cidr6;
+        }
+        _ => {
+            // ip4 and ip6
+            let value: &str = argument
+                .strip_prefix(':')
+                .ok_or_else(|| format!("{} needs an address: {}", mechanism, term))?;
+            let (address, length) = match value.split_once('/') {
+                Some((address, length)) => (address, Some(length)),
+                None => (value, None),
+            };
+
+            let ipv4: bool = mechanism == "ip4";
+            let parsed: Option<IpAddr> = address.parse::<IpAddr>().ok();
+
+            if parsed.map(|ip: IpAddr| ip.is_ipv4() != ipv4).unwrap_or(true) {
+                return Err(format!("Invalid address in {}", term));
+            }
+
+            directive.value = address.to_string();
+            let length: Option<u8> = length
+                .map(|length: &str| parse_cidr(length, if ipv4 { 32 } else { 128 }))
+                .transpose()?;
+
+            if ipv4 {
+                directive.cidr4 = length;
+            } else {
+                directive.cidr6 = length;
+            }
+        }
+    }
+
+    Ok(directive)
+}
+
+#[doc = "Parse a v=spf1 record; any syntax error is a permerror"]
+pub fn parse_record(record: &str) -> Result<SpfRecord, String> {
+    if !is_spf(record) {
+        return Err("Record does not start with v=spf1".to_string());
+    }
+
+    let mut parsed: SpfRecord = SpfRecord::default();
+
+    for term in record.split(' ').skip(1).filter(|term: &&str| !term.is_empty()) {
+        let modifier: Option<(&str, &str)> = term.split_once('=').filter(|(name, _value)| {
+            !name.is_empty() &&
+                name.chars().next().map(|c: char| c.is_ascii_alphabetic()).unwrap_or(false) &&
+                name.chars().all(|c: char| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        });
+
+        match modifier {
+            Some((name, value)) => {
+                let name: String = name.to_lowercase();
+
+                if !valid_macro_string(value, false) {
+                    return Err(format!("Invalid macro-string in {}", term));
+                }
+
+                match name.as_str() {
+                    "redirect" | "exp" if value.is_empty() || !valid_domain_spec(value) => {
+                        return Err(format!("Invalid domain-spec in {}", term));
+                    }
+                    "redirect" if parsed.redirect.is_some() => {
+                        return Err("Multiple redirect modifiers".to_string());
+                    }
+                    "exp" if parsed.exp.is_some() => {
+                        return Err("Multiple exp modifiers".to_string());
+                    }
+                    "redirect" => {
+                        parsed.redirect = Some(value.to_string());
+                    }
+                    "exp" => {
+                        parsed.exp = Some(value.to_string());
+                    }
+                    // Unknown modifiers are ignored
+                    _ => {}
+                }
+            }
+            None => {
+                parsed.directives.push(parse_directive(term)?);
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn spf_records(txt: Vec<String>) -> Vec<String> {
+    txt.into_iter()
+        .filter(|record: &String| is_spf(record))
+        .collect()
+}
+
+pub fn fetch_record(resolver: &Resolver, domain: &str) -> Result<Vec<String>> {
+    Ok(spf_records(txt_records(resolver, domain)?))
+}
+
+fn exchange_names(rdata: &[RData]) -> Vec<String> {
+    rdata
+        .iter()
+        .filter_map(|rdata: &RData| {
+            match rdata {
+                RData::MX(mx) => Some(mx.exchange().to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+pub fn mx_names(resolver: &Resolver, name: &str) -> Result<Vec<String>> {
+    Ok(exchange_names(&lookup_rdata(resolver, name, RecordType::MX)?))
+}
+
+#[doc = "Walks a record and everything it includes or redirects to, counting lookups and collecting authorised ranges"]
+struct Expansion<'a> {
+    resolver: &'a Resolver,
+    spf: Spf,
+}
+
+impl Expansion<'_> {
+    fn lookup(&mut self) -> bool {
+        self.spf.dns_lookups += 1;
+
+        if self.spf.dns_lookups > LOOKUP_LIMIT && !self.spf.lookup_limit_exceeded {
+            self.spf.lookup_limit_exceeded = true;
+            self.spf.errors.push(format!("More than {} DNS lookups", LOOKUP_LIMIT));
+        }
+
+        !self.spf.lookup_limit_exceeded
+    }
+
+    fn void(&mut self, empty: bool) {
+        if !empty {
+            return;
+        }
+
+        self.spf.void_lookups += 1;
+
+        if self.spf.void_lookups > VOID_LOOKUP_LIMIT && !self.spf.void_limit_exceeded {
+            self.spf.void_limit_exceeded = true;
+            self.spf.errors.push(format!("More than {} void lookups", VOID_LOOKUP_LIMIT));
+        }
+    }
+
+    fn temperror(&mut self, error: anyhow::Error) {
+        self.spf.temperror = true;
+        self.spf.errors.push(error.to_string());
+    }
+
+    fn authorize(&mut self, ip: IpAddr, cidr4: Option<u8>, cidr6: Option<u8>) {
+        match ip {
+            IpAddr::V4(_) => {
+                let range: String = match cidr4 {
+                    Some(cidr) => format!("{}/{}", ip, cidr),
+                    None => ip.to_string(),
+                };
+                if !self.spf.ip4.contains(&range) {
+                    self.spf.ip4.push(range);
+                }
+            }
+            IpAddr::V6(_) => {
+                let range: String = match cidr6 {
+                    Some(cidr) => format!("{}/{}", ip, cidr),
+                    None => ip.to_string(),
+                };
+                if !self.spf.ip6.contains(&range) {
+                    self.spf.ip6.push(range);
+                }
+            }
+        }
+    }
+
+    fn host_addresses(&mut self, name: &str) -> Option<Vec<IpAddr>> {
+        let mut found: Vec<IpAddr> = Vec::new();
+
+        for record_type in [RecordType::A, RecordType::AAAA] {
+            match addresses(self.resolver, name, record_type) {
+                Ok(addresses) => found.extend(addresses),
+                Err(e) => {
+                    self.temperror(e);
+                    return None;
+                }
+            }
+        }
+
+        Some(found)
+    }
+
+    #[doc = "Expand the record at domain; authorized is false below a non-pass include so its ranges are not listed"]
+    fn expand(&mut self, domain: &str, authorized: bool, source: &str) -> Option<String> {
+        let records: Vec<String> = match fetch_record(self.resolver, domain) {
+            Ok(records) => records,
+            Err(e) => {
+                self.temperror(e);
+                return None;
+            }
+        };
+
+        if source != "record" {
+            self.void(records.is_empty());
+            self.spf.includes.push(SpfInclude {
+                domain: domain.to_string(),
+                mechanism: source.to_string(),
+                record: records.first().cloned().unwrap_or_default(),
+            });
+        }
+
+        let record: SpfRecord = match records.as_slice() {
+            [record] =>
+                match parse_record(record) {
+                    Ok(record) => record,
+                    Err(e) => {
+                        self.spf.errors.push(format!("{}: {}", domain, e));
+                        return None;
+                    }
+                }
+            [] => {
+                self.spf.errors.push(format!("No SPF record at {}", domain));
+                return None;
+            }
+            _ => {
+                self.spf.errors.push(format!("Multiple SPF records at {}", domain));
+                return None;
+            }
+        };
+
+        let mut all: Option<String> = None;
+
+        for directive in &record.directives {
+            let pass: bool = authorized && directive.qualifier == '+';
+            let target: String = if directive.value.is_empty() {
+                domain.to_string()
+            } else {
+                directive.value.clone()
+            };
+
+            match directive.mechanism.as_str() {
+                "all" => {
+                    all = Some(directive.to_string());
+                    break;
+                }
+                "ip4" | "ip6" => {
+                    if pass {
+                        if let Ok(ip) = directive.value.parse::<IpAddr>() {
+                            self.authorize(ip, directive.cidr4, directive.cidr6);
+                        }
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            if !self.lookup() {
+                return None;
+            }
+
+            // Macros depend on the connecting client and cannot be flattened
+            if target.contains('%') || directive.mechanism == "ptr" || directive.mechanism == "exists" {
+                self.spf.unexpanded.push(directive.to_string());
+                continue;
+            }
+
+            match directive.mechanism.as_str() {
+                "include" => {
+                    self.expand(&target, pass, "include");
+                }
+                "a" => {
+                    if let Some(found) = self.host_addresses(&target) {
+                        self.void(found.is_empty());
+                        if pass {
+                            for ip in found {
+                                self.authorize(ip, directive.cidr4, directive.cidr6);
+                            }
+                        }
+                    }
+                }
+                "mx" => {
+                    let names: Vec<String> = match mx_names(self.resolver, &target) {
+                        Ok(names) => names,
+                        Err(e) => {
+                            self.temperror(e);
+                            continue;
+                        }
+                    };
+                    self.void(names.is_empty());
+
+                    if names.len() > 10 {
+                        self.spf.errors.push(format!("More than 10 MX hosts for {}", target));
+                    }
+
+                    for name in names.iter().take(10) {
+                        if let Some(found) = self.host_addresses(name) {
+                            if pass {
+                                for ip in found {
+                                    self.authorize(ip, directive.cidr4, directive.cidr6);
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // redirect is only used when the record has no all mechanism
+        if let (None, Some(redirect)) = (&all, &record.redirect) {
+            if !self.lookup() {
+                return None;
+            }
+            if redirect.contains('%') {
+                self.spf.unexpanded.push(format!("redirect={}", redirect));
+                return None;
+            }
+            return self.expand(redirect, authorized, "redirect");
+        }
+
+        all
+    }
+}
+
+#[doc = "Unexpanded Spf result for the TXT records found at a domain"]
+pub fn spf(records: Vec<String>) -> Spf {
+    Spf {
+        multiple_records: records.len() > 1,
+        record: records.first().cloned().unwrap_or_default(),
+        records,
+        valid: false,
+        permerror: false,
+        temperror: false,
+        all: String::new(),
+        weak_all: false,
+        dns_lookups: 0,
+        void_lookups: 0,
+        lookup_limit_exceeded: false,
+        void_limit_exceeded: false,
+        includes: Vec::new(),
+        ip4: Vec::new(),
+        ip6: Vec::new(),
+        unexpanded: Vec::new(),
+        errors: Vec::new(),
+        error: String::new(),
+    }
+}
+
+fn expand_spf(domain: &str) -> Result<Spf> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let records: Vec<String> = fetch_record(&resolver, domain)?;
+
+    let mut expansion: Expansion = Expansion {
+        resolver: &resolver,
+        spf: spf(records),
+    };
+
+    if expansion.spf.records.is_empty() {
+        return Ok(expansion.spf);
+    }
+
+    let all: Option<String> = expansion.expand(domain, true, "record");
+    let mut spf: Spf = expansion.spf;
+
+    spf.all = all.unwrap_or_default();
+    // No all at all leaves the default result neutral, just like ?all
+    spf.weak_all = !spf.all.starts_with('-') && !spf.all.starts_with('~');
+    spf.permerror = !spf.temperror && !spf.errors.is_empty();
+    spf.valid = !spf.permerror && !spf.temperror;
+
+    Ok(spf)
+}
+
+#[doc = "SPF record of a domain expanded into its authorised ranges; a failed record lookup is a temperror with the reason in error"]
+pub fn check_spf(domain: &str) -> Spf {
+    let domain: &str = domain.trim_end_matches('.');
+
+    match expand_spf(domain) {
+        Ok(spf) => spf,
+        Err(e) => {
+            let mut spf: Spf = spf(Vec::new());
+            spf.temperror = true;
+            spf.error = e.to_string();
+            spf
+        }
+    }
+}
+
+#[doc = "Parameters of check_host() that macros can refer to"]
+pub struct Client {
+    pub ip: IpAddr,
+    pub sender: String,
+    pub helo: String,
+}
+
+impl Client {
+    pub fn new(ip: IpAddr, sender: &str, helo: &str) -> Self {
+        // An empty MAIL FROM is checked as postmaster at the HELO name
+        let sender: String = if sender.is_empty() {
+            format!("postmaster@{}", helo)
+        } else if !sender.contains('@') {
+            format!("postmaster@{}", sender)
+        } else {
+            sender.to_string()
+        };
+
+        Client { ip, sender, helo: helo.to_string() }
+    }
+
+    fn local_part(&self) -> &str {
+        match self.sender.rsplit_once('@') {
+            Some(("", _domain)) => "postmaster",
+            Some((local, _domain)) => local,
+            None => "postmaster",
+        }
+    }
+
+    fn sender_domain(&self) -> &str {
+        self.sender.rsplit_once('@').map(|(_local, domain)| domain).unwrap_or(&self.sender)
+    }
+}
+
+#[doc = "%{i}: dotted quad for IPv4, dot separated nibbles for IPv6"]
+fn macro_ip(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(_) => ip.to_string(),
+        IpAddr::V6(_) => {
+            let name: String = ptr_name(ip);
+            let mut nibbles: Vec<&str> = name.trim_end_matches(".ip6.arpa").split('.').collect();
+            nibbles.reverse();
+            nibbles.join(".")
+        }
+    }
+}
+
+fn url_escape(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte: u8| {
+            if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+#[doc = "Apply the digit, reverse and delimiter transformers of a macro to its value"]
+fn transform(value: &str, digits: Option<usize>, reverse: bool, delimiters: &str) -> String {
+    let delimiters: &str = if delimiters.is_empty() { "." } else { delimiters };
+    let mut parts: Vec<&str> = value.split(|c: char| delimiters.contains(c)).collect();
+
+    if reverse {
+        parts.reverse();
+    }
+    if let Some(digits) = digits {
+        if digits > 0 && digits < parts.len() {
+            parts = parts.split_off(parts.len() - digits);
+        }
+    }
+
+    parts.join(".")
+}
+
+pub fn expand_macros(value: &str, domain: &str, client: &Client, validated: &dyn Fn() -> String) -> String {
+    let mut expanded: String = String::new();
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('_') => expanded.push(' '),
+            Some('-') => expanded.push_str("%20"),
+            Some('{') => {
+                let letter: char = chars.next().unwrap_or('?');
+                let mut digits: String = String::new();
+                while let Some(digit) = chars.next_if(|c: &char| c.is_ascii_digit()) {
+                    digits.push(digit);
+                }
+                let reverse: bool = chars.next_if(|c: &char| c.eq_ignore_ascii_case(&'r')).is_some();
+                let mut delimiters: String = String::new();
+                for delimiter in chars.by_ref() {
+                    if delimiter == '}' {
+                        break;
+                    }
+                    delimiters.push(delimiter);
+                }
+
+                let raw: String = match letter.to_ascii_lowercase() {
+                    's' => client.sender.clone(),
+                    'l' => client.local_part().to_string(),
+                    'o' => client.sender_domain().to_string(),
+                    'd' => domain.to_string(),
+                    'i' => macro_ip(client.ip),
+                    'p' => validated(),
+                    'v' => (if client.ip.is_ipv4() { "in-addr" } else { "ip6" }).to_string(),
+                    'h' => client.helo.clone(),
+                    'c' => client.ip.to_string(),
+                    'r' => "unknown".to_string(),
+                    't' =>
+                        SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .unwrap_or(0)
+                            .to_string(),
+                    _ => String::new(),
+                };
+
+                let value: String = transform(&raw, digits.parse().ok(), reverse, &delimiters);
+
+                if letter.is_ascii_uppercase() {
+                    expanded.push_str(&url_escape(&value));
+                } else {
+                    expanded.push_str(&value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    expanded
+}
+
+#[doc = "Expanded domain-spec, with left-most labels dropped until it fits in 253 characters"]
+fn target_name(expanded: &str) -> String {
+    let mut name: &str = expanded.trim_end_matches('.');
+
+    while name.len() > 253 {
+        name = name.split_once('.').map(|(_label, rest)| rest).unwrap_or("");
+    }
+
+    name.to_string()
+}
+
+pub fn in_network(ip: IpAddr, network: IpAddr, length: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask: u32 = if length == 0 { 0 } else { u32::MAX << (32 - (length as u32).min(32)) };
+            (u32::from(ip) & mask) == (u32::from(network) & mask)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask: u128 = if length == 0 { 0 } else { u128::MAX << (128 - (length as u32).min(128)) };
+            (u128::from(ip) & mask) == (u128::from(network) & mask)
+        }
+        _ => false,
+    }
+}
+
+fn qualifier_result(qualifier: char) -> &'static str {
+    match qualifier {
+        '-' => "fail",
+        '~' => "softfail",
+        '?' => "neutral",
+        _ => "pass",
+    }
+}
+
+#[doc = "Terminal check_host() outcome other than a mechanism match"]
+struct Abort(&'static str, String);
+
+#[doc = "DNS lookup used by check_host(), shaped like query::lookup_rdata"]
+type RdataLookup<'a> = dyn Fn(&str, RecordType) -> Result<Vec<RData>> + 'a;
+
+#[doc = "State of one check_host() run, lookup counters are shared across includes and redirects"]
+struct Evaluation<'a> {
+    lookup: &'a RdataLookup<'a>,
+    client: &'a Client,
+    lookups: u32,
+    void_lookups: u32,
+    includes: usize,
+    trace: Vec<String>,
+    mechanism: String,
+    explanation: String,
+}
+
+impl Evaluation<'_> {
+    fn addresses(&self, name: &str, record_type: RecordType) -> Result<Vec<IpAddr>> {
+        Ok(ip_addresses(&(self.lookup)(name, record_type)?))
+    }
+
+    fn txt(&self, name: &str) -> Result<Vec<String>> {
+        Ok(txt_strings(&(self.lookup)(name, RecordType::TXT)?))
+    }
+
+    fn log(&mut self, depth: usize, line: String) {
+        self.trace.push(format!("{}{}", "  ".repeat(depth), line));
+    }
+
+    fn lookup(&mut self) -> stdResult<(), Abort> {
+        self.lookups += 1;
+
+        if self.lookups > LOOKUP_LIMIT {
+            return Err(Abort("permerror", format!("More than {} DNS lookups", LOOKUP_LIMIT)));
+        }
+
+        Ok(())
+    }
+
+    fn void(&mut self, empty: bool) -> stdResult<(), Abort> {
+        if empty {
+            self.void_lookups += 1;
+        }
+
+        if self.void_lookups > VOID_LOOKUP_LIMIT {
+            return Err(Abort("permerror", format!("More than {} void lookups", VOID_LOOKUP_LIMIT)));
+        }
+
+        Ok(())
+    }
+
+    fn dns<T>(&self, result: Result<T>) -> stdResult<T, Abort> {
+        result.map_err(|e: anyhow::Error| Abort("temperror", e.to_string()))
+    }
+
+    fn family(&self) -> RecordType {
+        if self.client.ip.is_ipv4() { RecordType::A } else { RecordType::AAAA }
+    }
+
+    #[doc = "PTR names of the client that resolve back to its address, at most 10 are tried"]
+    fn validated_names(&self) -> Vec<String> {
+        let names: Vec<String> = (self.lookup)(&ptr_name(self.client.ip), RecordType::PTR)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|rdata: &RData| {
+                match rdata {
+                    RData::PTR(ptr) => Some(ptr.0.to_string().trim_end_matches('.').to_string()),
+                    _ => None,
+                }
+            })
+            .take(10)
+            .collect();
+
+        names
+            .into_iter()
+            .filter(|name: &String| {
+                self.addresses(name, self.family())
+                    .map(|found: Vec<IpAddr>| found.contains(&self.client.ip))
+                    .unwrap_or(false)
+            })
+            .collect()
+    }
+
+    fn expand(&self, value: &str, domain: &str) -> String {
+        // %{p} prefers a validated name within the domain, any validated name otherwise
+        let validated = || {
+            let names: Vec<String> = self.validated_names();
+            let suffix: String = format!(".{}", domain.to_lowercase());
+
+            names
+                .iter()
+                .find(|name: &&String| {
+                    name.eq_ignore_ascii_case(domain) || name.to_lowercase().ends_with(&suffix)
+                })
+                .or(names.first())
+                .cloned()
+                .unwrap_or("unknown".to_string())
+        };
+
+        target_name(&expand_macros(value, domain, self.client, &validated))
+    }
+
+    fn matches_addresses(&self, found: &[IpAddr], directive: &Directive) -> bool {
+        let length: u8 = if self.client.ip.is_ipv4() {
+            directive.cidr4.unwrap_or(32)
+        } else {
+            directive.cidr6.unwrap_or(128)
+        };
+
+        found.iter().any(|address: &IpAddr| in_network(self.client.ip, *address, length))
+    }
+
+    fn matches(&mut self, directive: &Directive, domain: &str, depth: usize) -> stdResult<bool, Abort> {
+        let target: String = if directive.value.is_empty() {
+            domain.to_string()
+        } else {
+            self.expand(&directive.value, domain)
+        };
+
+        match directive.mechanism.as_str() {
+            "all" => Ok(true),
+            "ip4" | "ip6" => {
+                let network: IpAddr = directive.value
+                    .parse()
+                    .map_err(|_e| Abort("permerror", format!("Invalid address {}", directive)))?;
+                let length: u8 = directive.cidr4.or(directive.cidr6).unwrap_or(if network.is_ipv4() { 32 } else { 128 });
+
+                Ok(in_network(self.client.ip, network, length))
+            }
+            "include" => {
+                self.lookup()?;
+
+                // The included record's exp= is not used, only that of the record being evaluated
+                self.includes += 1;
+                let outcome: stdResult<(&'static str, String), Abort> = self.check_host(&target, depth + 1);
+                self.includes -= 1;
+
+                match outcome {
+                    Ok(("pass", _)) => Ok(true),
+                    Ok(("fail", _)) | Ok(("softfail", _)) | Ok(("neutral", _)) => Ok(false),
+                    Ok((result, _)) => Err(Abort("permerror", format!("include:{} returned {}", target, result))),
+                    Err(Abort("temperror", reason)) => Err(Abort("temperror", reason)),
+                    Err(Abort(_, reason)) => Err(Abort("permerror", reason)),
+                }
+            }
+            "a" => {
+                self.lookup()?;
+                let found: Vec<IpAddr> = self.dns(self.addresses(&target, self.family()))?;
+                self.void(found.is_empty())?;
+
+                Ok(self.matches_addresses(&found, directive))
+            }
+            "mx" => {
+                self.lookup()?;
+                let names: Vec<String> = self.dns(
+                    (self.lookup)(&target, RecordType::MX).map(|rdata: Vec<RData>| exchange_names(&rdata))
+                )?;
+                self.void(names.is_empty())?;
+
+                if names.len() > 10 {
+                    return Err(Abort("permerror", format!("More than 10 MX hosts for {}", target)));
+                }
+
+                for name in names {
+                    let found: Vec<IpAddr> = self.dns(self.addresses(&name, self.family()))?;
+                    if self.matches_addresses(&found, directive) {
+                        return Ok(true);
+                    }
+                }
+
+                Ok(false)
+            }
+            "ptr" => {
+                self.lookup()?;
+                let target: String = target.to_lowercase();
+
+                Ok(
+                    self
+                        .validated_names()
+                        .iter()
+                        .map(|name: &String| name.to_lowercase())
+                        .any(|name: String| name == target || name.ends_with(&format!(".{}", target)))
+                )
+            }
+            "exists" => {
+                self.lookup()?;
+                let found: Vec<IpAddr> = self.dns(self.addresses(&target, RecordType::A))?;
+                self.void(found.is_empty())?;
+
+                Ok(!found.is_empty())
+            }
+            _ => Err(Abort("permerror", format!("Unknown mechanism {}", directive))),
+        }
+    }
+
+    fn explain(&mut self, exp: &str, domain: &str) {
+        let name: String = self.expand(exp, domain);
+
+        if let Ok([text]) = self.txt(&name).as_deref() {
+            if valid_macro_string(&text.replace(' ', "%_"), true) {
+                let validated = || "unknown".to_string();
+                self.explanation = expand_macros(text, domain, self.client, &validated);
+            }
+        }
+    }
+
+    fn check_host(&mut self, domain: &str, depth: usize) -> stdResult<(&'static str, String), Abort> {
+        let domain: String = domain.trim_end_matches('.').to_string();
+
+        let valid_domain: bool = domain.contains('.') &&
+            domain.split('.').all(|label: &str| !label.is_empty() && label.len() <= 63);
+        if !valid_domain {
+            self.log(depth, format!("{}: not a valid domain -> none", domain));
+            return Ok(("none", String::new()));
+        }
+
+        let records: Vec<String> = self.dns(self.txt(&domain).map(spf_records))?;
+        let record: SpfRecord = match records.as_slice() {
+            [] => {
+                self.log(depth, format!("{}: no SPF record -> none", domain));
+                return Ok(("none", String::new()));
+            }
+            [record] => {
+                self.log(depth, format!("{}: {}", domain, record));
+                parse_record(record).map_err(|e: String| Abort("permerror", e))?
+            }
+            _ => {
+                return Err(Abort("permerror", format!("Multiple SPF records at {}", domain)));
+            }
+        };
+
+        for directive in &record.directives {
+            if self.matches(directive, &domain, depth)? {
+                let result: &'static str = qualifier_result(directive.qualifier);
+                self.log(depth, format!("{} matched -> {}", directive, result));
+
+                // exp= of a redirect target applies as if the record had been published at the domain
+                if result == "fail" && self.includes == 0 {
+                    if let Some(exp) = &record.exp {
+                        self.explain(exp, &domain);
+                    }
+                }
+
+                return Ok((result, directive.to_string()));
+            }
+
+            self.log(depth, format!("{} no match", directive));
+        }
+
+        if let Some(redirect) = &record.redirect {
+            self.lookup()?;
+            let target: String = self.expand(redirect, &domain);
+            self.log(depth, format!("redirect={}", target));
+
+            return match self.check_host(&target, depth + 1)? {
+                ("none", _) => Err(Abort("permerror", format!("redirect to {} without SPF record", target))),
+                outcome => Ok(outcome),
+            };
+        }
+
+        self.log(depth, "no mechanism matched -> neutral".to_string());
+        Ok(("neutral", String::new()))
+    }
+}
+
+#[doc = "RFC 7208 check_host(): would mail from sender, sent by ip introducing itself as helo, pass SPF for domain"]
+pub fn evaluate_spf(domain: &str, ip: &str, helo: &str, sender: &str) -> Result<SpfEvaluation> {
+    let ip: IpAddr = ip.trim().parse().map_err(|_e| Error::msg(format!("Invalid IP address {}", ip)))?;
+    let client: Client = Client::new(ip, sender, helo);
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+
+    let lookup = |name: &str, record_type: RecordType| lookup_rdata(&resolver, name, record_type);
+
+    Ok(evaluate(domain, &client, &lookup))
+}
+
+fn evaluate(domain: &str, client: &Client, lookup: &RdataLookup) -> SpfEvaluation {
+    let mut evaluation: Evaluation = Evaluation {
+        lookup,
+        client,
+        lookups: 0,
+        void_lookups: 0,
+        includes: 0,
+        trace: Vec::new(),
+        mechanism: String::new(),
+        explanation: String::new(),
+    };
+
+    let result: &str = match evaluation.check_host(domain, 0) {
+        Ok((result, mechanism)) => {
+            evaluation.mechanism = mechanism;
+            result
+        }
+        Err(Abort(result, reason)) => {
+            evaluation.log(0, format!("{} -> {}", reason, result));
+            result
+        }
+    };
+
+    SpfEvaluation {
+        domain: domain.trim_end_matches('.').to_string(),
+        ip: client.ip.to_string(),
+        sender: client.sender.clone(),
+        helo: client.helo.clone(),
+        result: result.to_string(),
+        mechanism: evaluation.mechanism,
+        explanation: evaluation.explanation,
+        dns_lookups: evaluation.lookups,
+        void_lookups: evaluation.void_lookups,
+        trace: evaluation.trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ evaluate, expand_macros, in_network, parse_record, valid_macro_string, Client };
+    use crate::types::SpfEvaluation;
+
+    use std::net::{ IpAddr, Ipv4Addr };
+
+    use anyhow::{ Error, Result };
+    use hickory_resolver::proto::rr::{ Name, RData, RecordType };
+    use hickory_resolver::proto::rr::rdata::{ A, MX, TXT };
+
+    #[test]
+    fn parses_mechanisms_and_modifiers() {
+        let record = parse_record(
+            "v=spf1 ip4:192.0.2.0/24 ip6:2001:db8::/32 a:mail.example.com/28//64 mx//64 -include:_spf.example.net ?exists:%{i}._spf.example.com redirect=_spf.example.org"
+        ).unwrap();
+
+        assert_eq!(record.directives.len(), 6);
+        assert_eq!(record.directives[1].value, "2001:db8::");
+        assert_eq!(record.directives[1].cidr6, Some(32));
+        assert_eq!(record.directives[2].cidr4, Some(28));
+        assert_eq!(record.directives[2].cidr6, Some(64));
+        assert_eq!(record.directives[3].cidr6, Some(64));
+        assert_eq!(record.directives[4].qualifier, '-');
+        assert_eq!(record.directives[4].to_string(), "-include:_spf.example.net");
+        assert_eq!(record.redirect.as_deref(), Some("_spf.example.org"));
+    }
+
+    #[test]
+    fn rejects_syntax_errors() {
+        assert!(parse_record("v=spf1 ip4:192.0.2.0/33").is_err());
+        assert!(parse_record("v=spf1 ip4:2001:db8::1").is_err());
+        assert!(parse_record("v=spf1 include").is_err());
+        assert!(parse_record("v=spf1 foo:bar").is_err());
+        assert!(parse_record("v=spf1 redirect=a.example redirect=b.example").is_err());
+        assert!(parse_record("v=spf10 -all").is_err());
+        assert!(parse_record("v=spf1 unknown-modifier=value -all").is_ok());
+    }
+
+    #[test]
+    fn validates_macros() {
+        assert!(valid_macro_string("%{ir}.%{v}._spf.%{d2}", false));
+        assert!(valid_macro_string("%{l1r-}.example.com", false));
+        assert!(!valid_macro_string("%{x}.example.com", false));
+        assert!(!valid_macro_string("%{c}.example.com", false));
+        assert!(valid_macro_string("%{c}", true));
+        assert!(!valid_macro_string("100%", false));
+    }
+
+    #[test]
+    fn expands_rfc_examples() {
+        let client: Client = Client::new(
+            "192.0.2.3".parse::<IpAddr>().unwrap(),
+            "strong-bad@email.example.com",
+            "mx.example.org"
+        );
+        let validated = || "unknown".to_string();
+        let expand = |value: &str| expand_macros(value, "email.example.com", &client, &validated);
+
+        assert_eq!(expand("%{s}"), "strong-bad@email.example.com");
+        assert_eq!(expand("%{o}"), "email.example.com");
+        assert_eq!(expand("%{d4}"), "email.example.com");
+        assert_eq!(expand("%{d2}"), "example.com");
+        assert_eq!(expand("%{dr}"), "com.example.email");
+        assert_eq!(expand("%{d2r}"), "example.email");
+        assert_eq!(expand("%{l}"), "strong-bad");
+        assert_eq!(expand("%{l-}"), "strong.bad");
+        assert_eq!(expand("%{lr-}"), "bad.strong");
+        assert_eq!(expand("%{l1r-}"), "strong");
+        assert_eq!(expand("%{ir}.%{v}._spf.%{d2}"), "3.2.0.192.in-addr._spf.example.com");
+        assert_eq!(expand("%{lr-}.lp._spf.%{d2}"), "bad.strong.lp._spf.example.com");
+
+        let client: Client = Client::new("2001:db8::cb01".parse::<IpAddr>().unwrap(), "", "mx.example.org");
+        let expand = |value: &str| expand_macros(value, "email.example.com", &client, &validated);
+
+        assert_eq!(
+            expand("%{ir}.%{v}._spf.%{d2}"),
+            "1.0.b.c.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6._spf.example.com"
+        );
+        assert_eq!(expand("%{s}"), "postmaster@mx.example.org");
+    }
+
+    #[test]
+    fn matches_networks() {
+        let ip: IpAddr = "192.0.2.130".parse().unwrap();
+
+        assert!(in_network(ip, "192.0.2.0".parse().unwrap(), 24));
+        assert!(!in_network(ip, "192.0.2.0".parse().unwrap(), 25));
+        assert!(in_network(ip, "10.0.0.0".parse().unwrap(), 0));
+        assert!(!in_network(ip, "2001:db8::".parse().unwrap(), 0));
+        assert!(in_network("2001:db8::1".parse().unwrap(), "2001:db8::".parse().unwrap(), 32));
+    }
+
+    #[doc = "check_host() for client against a zone of (name, record) pairs; a TXT lookup of broken.example.test fails"]
+    fn check(zone: &[(&str, &str)], domain: &str, client: &str) -> SpfEvaluation {
+        let lookup = |name: &str, record_type: RecordType| -> Result<Vec<RData>> {
+            let name: String = name.trim_end_matches('.').to_lowercase();
+
+            if name == "broken.example.test" {
+                return Err(Error::msg("SERVFAIL"));
+            }
+
+            Ok(
+                zone
+                    .iter()
+                    .filter(|(owner, _)| *owner == name)
+                    .filter_map(|(_, record)| {
+                        let rdata: RData = if let Some(text) = record.strip_prefix("TXT ") {
+                            RData::TXT(TXT::new(vec![text.to_string()]))
+                        } else if let Some(exchange) = record.strip_prefix("MX ") {
+                            RData::MX(MX::new(10, Name::from_ascii(exchange).unwrap()))
+                        } else {
+                            RData::A(A(record.strip_prefix("A ")?.parse::<Ipv4Addr>().unwrap()))
+                        };
+
+                        (rdata.record_type() == record_type).then_some(rdata)
+                    })
+                    .collect()
+            )
+        };
+
+        let client: Client = Client::new(client.parse::<IpAddr>().unwrap(), "user@example.test", "mail.example.test");
+        evaluate(domain, &client, &lookup)
+    }
+
+    #[test]
+    fn follows_include() {
+        let zone: [(&str, &str); 3] = [
+            ("example.test", "TXT v=spf1 include:_spf.example.test -all"),
+            ("_spf.example.test", "TXT v=spf1 a:mail.example.test -all"),
+            ("mail.example.test", "A 192.0.2.10"),
+        ];
+
+        let passed = check(&zone, "example.test", "192.0.2.10");
+        assert_eq!(passed.result, "pass", "{:?}", passed.trace);
+        assert_eq!(passed.mechanism, "include:_spf.example.test");
+        assert_eq!(passed.dns_lookups, 2);
+
+        let failed = check(&zone, "example.test", "198.51.100.1");
+        assert_eq!(failed.result, "fail", "{:?}", failed.trace);
+        assert_eq!(failed.mechanism, "-all");
+    }
+
+    #[test]
+    fn include_without_record_is_permerror() {
+        let zone: [(&str, &str); 1] = [("example.test", "TXT v=spf1 include:missing.example.test -all")];
+
+        assert_eq!(check(&zone, "example.test", "192.0.2.10").result, "permerror");
+    }
+
+    #[test]
+    fn follows_redirect() {
+        let zone: [(&str, &str); 4] = [
+            ("example.test", "TXT v=spf1 redirect=_spf.example.test"),
+            ("_spf.example.test", "TXT v=spf1 mx -all"),
+            ("_spf.example.test", "MX mail.example.test"),
+            ("mail.example.test", "A 192.0.2.10"),
+        ];
+
+        let passed = check(&zone, "example.test", "192.0.2.10");
+        assert_eq!(passed.result, "pass", "{:?}", passed.trace);
+        assert_eq!(passed.mechanism, "mx");
+        assert_eq!(passed.dns_lookups, 2);
+
+        let missing: [(&str, &str); 1] = [("example.test", "TXT v=spf1 redirect=missing.example.test")];
+        assert_eq!(check(&missing, "example.test", "192.0.2.10").result, "permerror");
+    }
+
+    #[test]
+    fn stops_after_ten_lookups() {
+        let includes = |count: u32| -> String {
+            let terms: Vec<String> = (1..=count).map(|n: u32| format!("include:i{}.example.test", n)).collect();
+            format!("TXT v=spf1 {} -all", terms.join(" "))
+        };
+        let (ten, eleven): (String, String) = (includes(10), includes(11));
+        let names: Vec<String> = (1..=11).map(|n: u32| format!("i{}.example.test", n)).collect();
+        let mut zone: Vec<(&str, &str)> = names
+            .iter()
+            .map(|name: &String| (name.as_str(), "TXT v=spf1 -all"))
+            .collect();
+
+        zone.push(("example.test", ten.as_str()));
+        let evaluation = check(&zone, "example.test", "192.0.2.10");
+        assert_eq!(evaluation.result, "fail", "{:?}", evaluation.trace);
+        assert_eq!(evaluation.dns_lookups, 10);
+
+        zone.pop();
+        zone.push(("example.test", eleven.as_str()));
+        let evaluation = check(&zone, "example.test", "192.0.2.10");
+        assert_eq!(evaluation.result, "permerror", "{:?}", evaluation.trace);
+        assert_eq!(evaluation.dns_lookups, 11);
+        assert!(evaluation.trace.last().unwrap().starts_with("More than 10 DNS lookups"));
+    }
+
+    #[test]
+    fn stops_after_two_void_lookups() {
+        let two: [(&str, &str); 1] = [("example.test", "TXT v=spf1 a:v1.example.test mx:v2.example.test -all")];
+        let evaluation = check(&two, "example.test", "192.0.2.10");
+        assert_eq!(evaluation.result, "fail", "{:?}", evaluation.trace);
+        assert_eq!(evaluation.void_lookups, 2);
+
+        let three: [(&str, &str); 1] = [
+            ("example.test", "TXT v=spf1 a:v1.example.test mx:v2.example.test exists:v3.example.test -all"),
+        ];
+        let evaluation = check(&three, "example.test", "192.0.2.10");
+        assert_eq!(evaluation.result, "permerror", "{:?}", evaluation.trace);
+        assert_eq!(evaluation.void_lookups, 3);
+    }
+
+    #[test]
+    fn failed_lookup_is_temperror() {
+        let zone: [(&str, &str); 1] = [("example.test", "TXT v=spf1 include:broken.example.test -all")];
+
+        assert_eq!(check(&zone, "example.test", "192.0.2.10").result, "temperror");
+        assert_eq!(check(&zone, "broken.example.test", "192.0.2.10").result, "temperror");
+    }
+
+    #[test]
+    fn explains_fail() {
+        let zone: [(&str, &str); 2] = [
+            ("example.test", "TXT v=spf1 ip4:192.0.2.0/24 -all exp=why.example.test"),
+            ("why.example.test", "TXT %{i} may not send mail for %{d}"),
+        ];
+
+        let failed = check(&zone, "example.test", "198.51.100.1");
+        assert_eq!(failed.result, "fail");
+        assert_eq!(failed.explanation, "198.51.100.1 may not send mail for example.test");
+
+        assert_eq!(check(&zone, "example.test", "192.0.2.10").explanation, "");
+    }
+
+    #[test]
+    fn explains_fail_of_redirect_target() {
+        let zone: [(&str, &str); 4] = [
+            ("example.test", "TXT v=spf1 redirect=_spf.example.test exp=outer.example.test"),
+            ("_spf.example.test", "TXT v=spf1 -all exp=why._spf.example.test"),
+            ("why._spf.example.test", "TXT %{d} says no"),
+            ("outer.example.test", "TXT not this one"),
+        ];
+
+        let failed = check(&zone, "example.test", "198.51.100.1");
+        assert_eq!(failed.result, "fail", "{:?}", failed.trace);
+        assert_eq!(failed.explanation, "_spf.example.test says no");
+    }
+
+    #[test]
+    fn ignores_exp_of_include() {
+        let zone: [(&str, &str); 4] = [
+            ("example.test", "TXT v=spf1 include:_spf.example.test -all exp=why.example.test"),
+            ("_spf.example.test", "TXT v=spf1 -all exp=inner.example.test"),
+            ("why.example.test", "TXT outer"),
+            ("inner.example.test", "TXT inner"),
+        ];
+
+        assert_eq!(check(&zone, "example.test", "198.51.100.1").explanation, "outer");
+        assert_eq!(check(&zone[1..], "_spf.example.test", "198.51.100.1").explanation, "inner");
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::{ Read, Write };
+use std::net::{ SocketAddr, TcpListener };
+use std::thread;
+
+use openssl::asn1::{ Asn1Time, Asn1Type };
+use openssl::hash::MessageDigest;
+use openssl::pkey::{ PKey, Private };
+use openssl::rsa::Rsa;
+use openssl::ssl::{ SslAcceptor, SslMethod };
+use openssl::x509::{ X509, X509NameBuilder, X509NameRef };
+
+fn sign(name: &X509NameRef) -> (X509, PKey<Private>) {
+    let key: PKey<Private> = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(name).unwrap();
+    builder.set_issuer_name(name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(30).unwrap()).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+    (builder.build(), key)
+}
+
+#[doc = "Certificate and key for host, signed by itself and valid for 30 days"]
+pub fn self_signed(host: &str) -> (X509, PKey<Private>) {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", host).unwrap();
+
+    sign(&name.build())
+}
+
+#[doc = "Self-signed certificate whose CN, an ASCII host, is a BMPString (UTF-16BE) rather than a UTF8String"]
+pub fn bmp_self_signed(host: &str) -> X509 {
+    let utf16: String = host
+        .encode_utf16()
+        .flat_map(|unit: u16| unit.to_be_bytes())
+        .map(char::from)
+        .collect();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text_with_type("CN", &utf16, Asn1Type::BMPSTRING).unwrap();
+
+    sign(&name.build()).0
+}
+
+#[doc = "TLS server side presenting a self-signed certificate for host"]
+pub fn acceptor(host: &str) -> SslAcceptor {
+    let (cert, key) = self_signed(host);
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+    acceptor.set_certificate(&cert).unwrap();
+    acceptor.set_private_key(&key).unwrap();
+
+    acceptor.build()
+}
+
+#[doc = "One-shot HTTPS server with a self-signed certificate for host that answers any request with response"]
+pub fn stand_in(host: &str, response: &'static str) -> SocketAddr {
+    let acceptor: SslAcceptor = acceptor(host);
+    let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut tls = acceptor.accept(stream).unwrap();
+        let mut request: [u8; 1024] = [0; 1024];
+        let _ = tls.read(&mut request);
+        tls.write_all(response.as_bytes()).unwrap();
+        let _ = tls.shutdown();
+    });
+
+    addr
+}
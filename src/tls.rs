@@ -0,0 +1,121 @@
+use std::net::{ SocketAddr, TcpStream };
+use std::time::Duration;
+
+use anyhow::{ Result, Error };
+use openssl::ssl::{ SslConnector, SslConnectorBuilder, SslMethod, SslStream, SslVerifyMode, SslVersion };
+use openssl::x509::{ X509NameRef, X509Ref, X509VerifyResult };
+
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+pub const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn connect(addr: SocketAddr) -> Result<TcpStream> {
+    let stream: TcpStream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    Ok(stream)
+}
+
+#[doc = "Connector that completes the handshake regardless of certificate problems, see verify_result"]
+pub fn connector() -> Result<SslConnector> {
+    let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+    builder.set_verify(SslVerifyMode::NONE);
+
+    Ok(builder.build())
+}
+
+#[doc = "Connector builder restricted to a protocol range and cipher list, security level 0 so legacy versions and ciphers can be offered"]
+pub fn scan_builder(
+    min: SslVersion,
+    max: SslVersion,
+    cipher_list: &str,
+    ciphersuites: &str
+) -> Result<SslConnectorBuilder> {
+    let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+    builder.set_verify(SslVerifyMode::NONE);
+    builder.set_security_level(0);
+    builder.set_min_proto_version(Some(min))?;
+    builder.set_max_proto_version(Some(max))?;
+    builder.set_cipher_list(cipher_list)?;
+    builder.set_ciphersuites(ciphersuites)?;
+
+    Ok(builder)
+}
+
+pub fn scan_connector(
+    min: SslVersion,
+    max: SslVersion,
+    cipher_list: &str,
+    ciphersuites: &str
+) -> Result<SslConnector> {
+    Ok(scan_builder(min, max, cipher_list, ciphersuites)?.build())
+}
+
+pub fn handshake(stream: TcpStream, server_name: &str) -> Result<SslStream<TcpStream>> {
+    handshake_with(&connector()?, stream, server_name)
+}
+
+pub fn handshake_with(
+    connector: &SslConnector,
+    stream: TcpStream,
+    server_name: &str
+) -> Result<SslStream<TcpStream>> {
+    let name: &str = server_name.trim_end_matches('.');
+
+    connector
+        .configure()?
+        .connect(name, stream)
+        .map_err(|e| Error::msg(format!("TLS handshake failed: {}", e)))
+}
+
+pub fn verify_error(stream: &SslStream<TcpStream>) -> Option<String> {
+    let result: X509VerifyResult = stream.ssl().verify_result();
+
+    if result == X509VerifyResult::OK {
+        None
+    } else {
+        Some(result.error_string().to_string())
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte: &u8| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn name_to_string(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            format!(
+                "{}={}",
+                entry.object().nid().short_name().unwrap_or("?"),
+                entry
+                    .data()
+                    .as_utf8()
+                    .map(|data| data.to_string())
+                    .unwrap_or_default()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub fn subject(cert: &X509Ref) -> String {
+    name_to_string(cert.subject_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::name_to_string;
+    use crate::test_support::bmp_self_signed;
+
+    #[test]
+    fn formats_non_utf8_string_entries() {
+        let cert = bmp_self_signed("mx.example.test");
+
+        assert_eq!(name_to_string(cert.subject_name()), "CN=mx.example.test");
+        assert_eq!(name_to_string(cert.issuer_name()), "CN=mx.example.test");
+    }
+}
@@ -0,0 +1,205 @@
+use crate::types::{ ReportUri, TlsRpt };
+use crate::query::txt_records;
+
+use anyhow::Result;
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+
+fn valid_hostname(host: &str) -> bool {
+    host.contains('.') &&
+        host
+            .trim_end_matches('.')
+            .split('.')
+            .all(|label: &str| {
+                !label.is_empty() &&
+                    label.len() <= 63 &&
+                    label.chars().all(|c: char| c.is_ascii_alphanumeric() || c == '-')
+            })
+}
+
+#[doc = "Check a single reporting URI, mailto: needs local@domain and https: needs a host"]
+pub fn report_uri(uri: &str) -> ReportUri {
+    let mut result: ReportUri = ReportUri {
+        uri: uri.to_string(),
+        scheme: String::new(),
+        valid: false,
+        error: String::new(),
+    };
+
+    let (scheme, rest) = match uri.split_once(':') {
+        Some((scheme, rest)) => (scheme.to_lowercase(), rest),
+        None => {
+            result.error = "Missing URI scheme".to_string();
+            return result;
+        }
+    };
+    result.scheme = scheme.clone();
+
+    match scheme.as_str() {
+        "mailto" => {
+            // Query parameters are not part of the address
+            let address: &str = rest.split('?').next().unwrap_or("");
+
+            match address.rsplit_once('@') {
+                Some((local, domain)) if !local.is_empty() && valid_hostname(domain) => {
+                    result.valid = true;
+                }
+                _ => {
+                    result.error = format!("Invalid mailto address {}", address);
+                }
+            }
+        }
+        "https" => {
+            let host: &str = rest
+                .strip_prefix("//")
+                .and_then(|rest: &str| rest.split(['/', '?', '#']).next())
+                .map(|authority: &str| authority.rsplit('@').next().unwrap_or(""))
+                .map(|host: &str| host.split(':').next().unwrap_or(""))
+                .unwrap_or("");
+
+            if valid_hostname(host) {
+                result.valid = true;
+            } else {
+                result.error = format!("Invalid https URI {}", uri);
+            }
+        }
+        _ => {
+            result.error = format!("Unsupported scheme {}", scheme);
+        }
+    }
+
+    result
+}
+
+#[doc = "Parse _smtp._tls TXT records per RFC 8460; only exactly one v=TLSRPTv1 record is used by senders"]
+pub fn parse_tls_rpt(records: Vec<String>) -> TlsRpt {
+    let records: Vec<String> = records
+        .into_iter()
+        .filter(|record: &String| record.starts_with("v=TLSRPTv1"))
+        .collect();
+
+    let mut tls_rpt: TlsRpt = TlsRpt {
+        multiple_records: records.len() > 1,
+        records,
+        valid: false,
+        rua: Vec::new(),
+        duplicate_rua: Vec::new(),
+        errors: Vec::new(),
+        error: String::new(),
+    };
+
+    if tls_rpt.multiple_records {
+        tls_rpt.errors.push("Multiple v=TLSRPTv1 records".to_string());
+    }
+
+    let record: &str = match tls_rpt.records.first() {
+        Some(record) => record,
+        None => {
+            return tls_rpt;
+        }
+    };
+
+    let mut fields = record.split(';').map(|field: &str| field.trim());
+
+    if fields.next() != Some("v=TLSRPTv1") {
+        tls_rpt.errors.push("Record must start with v=TLSRPTv1".to_string());
+    }
+
+    let mut uris: Vec<String> = Vec::new();
+
+    for field in fields.filter(|field: &&str| !field.is_empty()) {
+        match field.split_once('=') {
+            Some((key, value)) if key.trim() == "rua" => {
+                uris.extend(
+                    value
+                        .split(',')
+                        .map(|uri: &str| uri.trim().to_string())
+                        .filter(|uri: &String| !uri.is_empty())
+                );
+            }
+            Some(_) => {}
+            None => {
+                tls_rpt.errors.push(format!("Malformed field {}", field));
+            }
+        }
+    }
+
+    if uris.is_empty() {
+        tls_rpt.errors.push("No rua reporting URI".to_string());
+    }
+
+    for (index, uri) in uris.iter().enumerate() {
+        if uris[..index].contains(uri) && !tls_rpt.duplicate_rua.contains(uri) {
+            tls_rpt.duplicate_rua.push(uri.clone());
+        }
+    }
+
+    tls_rpt.rua = uris
+        .iter()
+        .map(|uri: &String| report_uri(uri))
+        .collect();
+
+    tls_rpt.valid =
+        tls_rpt.errors.is_empty() && tls_rpt.rua.iter().all(|uri: &ReportUri| uri.valid);
+
+    tls_rpt
+}
+
+fn lookup_tls_rpt(domain: &str) -> Result<Vec<String>> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+
+    txt_records(&resolver, &format!("_smtp._tls.{}", domain.trim_end_matches('.')))
+}
+
+#[doc = "TLS-RPT record of a domain; a failed lookup is reported in error"]
+pub fn check_tls_rpt(domain: &str) -> TlsRpt {
+    match lookup_tls_rpt(domain) {
+        Ok(records) => parse_tls_rpt(records),
+        Err(e) => {
+            let mut tls_rpt: TlsRpt = parse_tls_rpt(Vec::new());
+            tls_rpt.error = e.to_string();
+            tls_rpt
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_tls_rpt;
+
+    #[test]
+    fn parses_valid_record() {
+        let tls_rpt = parse_tls_rpt(
+            vec!["v=TLSRPTv1; rua=mailto:tlsrpt@example.com,https://reports.example.com/v1".to_string()]
+        );
+
+        assert!(tls_rpt.valid);
+        assert_eq!(tls_rpt.rua.len(), 2);
+        assert_eq!(tls_rpt.rua[1].scheme, "https");
+    }
+
+    #[test]
+    fn flags_invalid_and_duplicate_uris() {
+        let tls_rpt = parse_tls_rpt(
+            vec![
+                "v=TLSRPTv1; rua=mailto:a@example.com,mailto:a@example.com,http://example.com".to_string(),
+                "v=TLSRPTv1; rua=mailto:b@example.com".to_string(),
+                "v=spf1 -all".to_string()
+            ]
+        );
+
+        assert!(!tls_rpt.valid);
+        assert!(tls_rpt.multiple_records);
+        assert_eq!(tls_rpt.duplicate_rua, vec!["mailto:a@example.com"]);
+        assert!(!tls_rpt.rua[2].valid);
+    }
+
+    #[test]
+    fn absent_record() {
+        let tls_rpt = parse_tls_rpt(Vec::new());
+
+        assert!(!tls_rpt.valid);
+        assert!(tls_rpt.records.is_empty());
+        assert!(tls_rpt.errors.is_empty());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::types::EncryptedTransport;
+use crate::http::{ request, HttpResponse };
+use crate::query::build_query;
+use crate::tls::{ connect, handshake, subject, verify_error };
+
+use std::io::{ Read, Write };
+use std::net::{ IpAddr, SocketAddr, TcpStream };
+
+use anyhow::{ Result, Error };
+use hickory_resolver::proto::op::{ Message, ResponseCode };
+use hickory_resolver::proto::rr::RecordType;
+use openssl::ssl::SslStream;
+
+const DOH_PATHS: [&str; 2] = ["/dns-query", "/doh"];
+
+fn encrypted_transport(protocol: &str, port: u16) -> EncryptedTransport {
+    EncryptedTransport {
+        protocol: protocol.to_string(),
+        port,
+        path: String::new(),
+        offered: false,
+        certificate_subject: String::new(),
+        certificate_not_before: String::new(),
+        certificate_not_after: String::new(),
+        certificate_valid: false,
+        certificate_error: String::new(),
+        query_succeeded: false,
+        error: String::new(),
+    }
+}
+
+fn record_certificate(transport: &mut EncryptedTransport, stream: &SslStream<TcpStream>) {
+    if let Some(cert) = stream.ssl().peer_certificate() {
+        transport.certificate_subject = subject(&cert);
+        transport.certificate_not_before = cert.not_before().to_string();
+        transport.certificate_not_after = cert.not_after().to_string();
+    }
+
+    match verify_error(stream) {
+        Some(error) => {
+            transport.certificate_error = error;
+        }
+        None => {
+            transport.certificate_valid = true;
+        }
+    }
+}
+
+fn answered(response: &[u8]) -> bool {
+    match Message::from_vec(response) {
+        Ok(message) => message.response_code() == ResponseCode::NoError,
+        Err(_e) => false,
+    }
+}
+
+fn dot_query(stream: &mut SslStream<TcpStream>, domain: &str) -> Result<bool> {
+    let query: Vec<u8> = build_query(domain, RecordType::SOA, false)?.to_vec()?;
+    stream.write_all(&(query.len() as u16).to_be_bytes())?;
+    stream.write_all(&query)?;
+
+    let mut length: [u8; 2] = [0; 2];
+    stream.read_exact(&mut length)?;
+    let mut response: Vec<u8> = vec![0; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut response)?;
+
+    Ok(answered(&response))
+}
+
+fn probe_dot(addr: SocketAddr, nsdomain: &str, domain: &str) -> EncryptedTransport {
+    let mut transport: EncryptedTransport = encrypted_transport("DoT", addr.port());
+
+    let mut stream: SslStream<TcpStream> = match
+        connect(addr).and_then(|tcp: TcpStream| handshake(tcp, nsdomain))
+    {
+        Ok(stream) => stream,
+        Err(e) => {
+            transport.error = e.to_string();
+            return transport;
+        }
+    };
+
+    transport.offered = true;
+    record_certificate(&mut transport, &stream);
+
+    match dot_query(&mut stream, domain) {
+        Ok(succeeded) => {
+            transport.query_succeeded = succeeded;
+        }
+        Err(e) => {
+            transport.error = e.to_string();
+        }
+    }
+
+    transport
+}
+
+fn probe_doh(addr: SocketAddr, nsdomain: &str, domain: &str) -> EncryptedTransport {
+    let mut transport: EncryptedTransport = encrypted_transport("DoH", addr.port());
+
+    let query: Vec<u8> = match
+        build_query(domain, RecordType::SOA, false).and_then(|message: Message|
+            message.to_vec().map_err(Error::from)
+        )
+    {
+        Ok(query) => query,
+        Err(e) => {
+            transport.error = e.to_string();
+            return transport;
+        }
+    };
+
+    for path in DOH_PATHS {
+        let mut stream: SslStream<TcpStream> = match
+            connect(addr).and_then(|tcp: TcpStream| handshake(tcp, nsdomain))
+        {
+            Ok(stream) => stream,
+            Err(e) => {
+                transport.error = e.to_string();
+                return transport;
+            }
+        };
+
+        record_certificate(&mut transport, &stream);
+
+        let response: HttpResponse = match
+            request(
+                &mut stream,
+                "POST",
+                nsdomain.trim_end_matches('.'),
+                path,
+                &[
+                    ("Content-Type", "application/dns-message"),
+                    ("Accept", "application/dns-message"),
+                ],
+                &query
+            )
+        {
+            Ok(response) => response,
+            Err(e) => {
+                transport.error = e.to_string();
+                continue;
+            }
+        };
+
+        let dns_message: bool = response
+            .header("Content-Type")
+            .map(|value: &str| value.starts_with("application/dns-message"))
+            .unwrap_or(false);
+
+        if response.status == 200 && dns_message {
+            transport.offered = true;
+            transport.path = path.to_string();
+            transport.query_succeeded = answered(&response.body);
+            transport.error = String::new();
+            break;
+        }
+
+        transport.error = format!("{} answered HTTP {}", path, response.status);
+    }
+
+    transport
+}
+
+#[doc = "Probe a nameserver address for DNS over TLS (853) and DNS over HTTPS (443)"]
+pub fn check_encrypted_transports(ip: IpAddr, nsdomain: &str, domain: &str) -> Vec<EncryptedTransport> {
+    vec![
+        probe_dot(SocketAddr::new(ip, 853), nsdomain, domain),
+        probe_doh(SocketAddr::new(ip, 443), nsdomain, domain)
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ probe_doh, probe_dot };
+    use crate::test_support::acceptor;
+
+    use std::io::{ Read, Write };
+    use std::net::{ SocketAddr, TcpListener, TcpStream };
+    use std::sync::mpsc::{ channel, Receiver, Sender };
+    use std::thread;
+
+    use hickory_resolver::proto::op::{ Message, MessageType, ResponseCode };
+    use hickory_resolver::proto::rr::RecordType;
+    use openssl::ssl::{ SslAcceptor, SslStream };
+
+    const NSDOMAIN: &str = "ns1.example.test.";
+
+    #[doc = "TLS nameserver for ns1.example.test serving connections with serve, which reports each query it answered"]
+    fn stand_in(
+        connections: usize,
+        serve: fn(&mut SslStream<TcpStream>, &Sender<String>)
+    ) -> (SocketAddr, Receiver<String>) {
+        let acceptor: SslAcceptor = acceptor(NSDOMAIN.trim_end_matches('.'));
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let (sender, receiver) = channel::<String>();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let mut tls = acceptor.accept(stream.unwrap()).unwrap();
+                serve(&mut tls, &sender);
+                let _ = tls.shutdown();
+            }
+        });
+
+        (addr, receiver)
+    }
+
+    #[doc = "Answer a wire format query with response_code, reporting its question as name and type"]
+    fn answer(query: &[u8], response_code: ResponseCode, queries: &Sender<String>) -> Vec<u8> {
+        let request: Message = Message::from_vec(query).unwrap();
+        let question = &request.queries()[0];
+        queries.send(format!("{} {}", question.name(), question.query_type())).unwrap();
+
+        let mut response: Message = Message::new();
+        response
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .set_response_code(response_code)
+            .add_queries(request.queries().to_vec());
+
+        response.to_vec().unwrap()
+    }
+
+    fn dot_answer(stream: &mut SslStream<TcpStream>, response_code: ResponseCode, queries: &Sender<String>) {
+        let mut length: [u8; 2] = [0; 2];
+        stream.read_exact(&mut length).unwrap();
+        let mut query: Vec<u8> = vec![0; u16::from_be_bytes(length) as usize];
+        stream.read_exact(&mut query).unwrap();
+
+        let response: Vec<u8> = answer(&query, response_code, queries);
+        stream.write_all(&(response.len() as u16).to_be_bytes()).unwrap();
+        stream.write_all(&response).unwrap();
+    }
+
+    #[doc = "Read an HTTP request, returning the request line and the body"]
+    fn http_request(stream: &mut SslStream<TcpStream>) -> (String, Vec<u8>) {
+        let mut raw: Vec<u8> = Vec::new();
+        let mut byte: [u8; 1] = [0; 1];
+
+        while !raw.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            raw.push(byte[0]);
+        }
+
+        let head: String = String::from_utf8(raw).unwrap();
+        let length: usize = head
+            .lines()
+            .find_map(|line: &str| line.strip_prefix("Content-Length: "))
+            .map(|value: &str| value.parse().unwrap())
+            .unwrap_or(0);
+        let mut body: Vec<u8> = vec![0; length];
+        stream.read_exact(&mut body).unwrap();
+
+        (head.lines().next().unwrap_or("").to_string(), body)
+    }
+
+    fn doh_answer(stream: &mut SslStream<TcpStream>, queries: &Sender<String>) {
+        let (request_line, body) = http_request(stream);
+
+        if !request_line.starts_with("POST /doh ") {
+            stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").unwrap();
+            return;
+        }
+
+        let response: Vec<u8> = answer(&body, ResponseCode::NoError, queries);
+        let head: String = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: {}\r\n\r\n",
+            response.len()
+        );
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(&response).unwrap();
+    }
+
+    #[test]
+    fn queries_over_dot() {
+        let (addr, queries) = stand_in(1, |stream, queries| dot_answer(stream, ResponseCode::NoError, queries));
+        let transport = probe_dot(addr, NSDOMAIN, "example.test.");
+
+        assert!(transport.offered, "{}", transport.error);
+        assert!(transport.query_succeeded, "{}", transport.error);
+        assert_eq!(transport.protocol, "DoT");
+        assert_eq!(transport.port, addr.port());
+        assert_eq!(queries.recv().unwrap(), format!("example.test. {}", RecordType::SOA));
+
+        assert_eq!(transport.certificate_subject, "CN=ns1.example.test");
+        assert!(!transport.certificate_not_before.is_empty());
+        assert!(!transport.certificate_not_after.is_empty());
+        assert!(!transport.certificate_valid);
+        assert!(!transport.certificate_error.is_empty());
+    }
+
+    #[test]
+    fn refused_dot_query_fails() {
+        let (addr, queries) = stand_in(1, |stream, queries| dot_answer(stream, ResponseCode::Refused, queries));
+        let transport = probe_dot(addr, NSDOMAIN, "example.test.");
+
+        assert!(transport.offered);
+        assert!(!transport.query_succeeded);
+        assert!(queries.recv().is_ok());
+    }
+
+    #[test]
+    fn dot_not_offered() {
+        let addr: SocketAddr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let transport = probe_dot(addr, NSDOMAIN, "example.test.");
+
+        assert!(!transport.offered && !transport.query_succeeded);
+        assert!(!transport.error.is_empty());
+        assert!(transport.certificate_subject.is_empty());
+    }
+
+    #[test]
+    fn queries_over_doh_on_second_path() {
+        let (addr, queries) = stand_in(2, doh_answer);
+        let transport = probe_doh(addr, NSDOMAIN, "example.test.");
+
+        assert!(transport.offered, "{}", transport.error);
+        assert!(transport.query_succeeded);
+        assert!(transport.error.is_empty(), "{}", transport.error);
+        assert_eq!(transport.protocol, "DoH");
+        assert_eq!(transport.path, "/doh");
+        assert_eq!(queries.recv().unwrap(), format!("example.test. {}", RecordType::SOA));
+
+        assert_eq!(transport.certificate_subject, "CN=ns1.example.test");
+        assert!(!transport.certificate_not_after.is_empty());
+        assert!(!transport.certificate_valid);
+    }
+
+    #[test]
+    fn doh_needs_dns_message() {
+        let (addr, _queries) = stand_in(2, |stream, _queries| {
+            http_request(stream);
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html></html>").unwrap();
+        });
+        let transport = probe_doh(addr, NSDOMAIN, "example.test.");
+
+        assert!(!transport.offered && !transport.query_succeeded);
+        assert_eq!(transport.error, "/doh answered HTTP 200");
+    }
+}
@@ -0,0 +1,307 @@
+use crate::types::{ TtlFinding, TtlRecord, TtlReport };
+use crate::query::{ nameserver_addresses, query };
+
+use std::env::var;
+use std::net::IpAddr;
+
+use anyhow::{ Result, Error };
+use hickory_resolver::Resolver;
+use hickory_resolver::config::{ ResolverConfig, ResolverOpts };
+use hickory_resolver::proto::op::Message;
+use hickory_resolver::proto::rr::{ Record, RecordType };
+
+#[doc = "TTL thresholds, overridable per deployment with TTL_MIN, TTL_MAX, TTL_MIN_<TYPE> and TTL_MAX_<TYPE>"]
+pub struct TtlPolicy {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl TtlPolicy {
+    pub fn for_type(record_type: RecordType) -> TtlPolicy {
+        let (min, max): (u32, u32) = match record_type {
+            RecordType::NS | RecordType::DS => (3600, 172800),
+            RecordType::SOA | RecordType::DNSKEY => (3600, 86400),
+            _ => (300, 86400),
+        };
+
+        let min: u32 = env_u32(&format!("TTL_MIN_{}", record_type))
+            .or_else(|| env_u32("TTL_MIN"))
+            .unwrap_or(min);
+        let max: u32 = env_u32(&format!("TTL_MAX_{}", record_type))
+            .or_else(|| env_u32("TTL_MAX"))
+            .unwrap_or(max);
+
+        TtlPolicy { min, max }
+    }
+}
+
+fn env_u32(key: &str) -> Option<u32> {
+    var(key).ok().and_then(|value| value.trim().parse().ok())
+}
+
+pub fn check_ttl(domain: &str) -> Result<TtlReport> {
+    let resolver: Resolver = Resolver::new(ResolverConfig::quad9(), ResolverOpts::default())?;
+    let servers: Vec<IpAddr> = nameserver_addresses(&resolver, domain)?;
+
+    let www_domain: String = format!("www.{}", domain);
+    let rrsets: Vec<(&str, RecordType)> = vec![
+        (domain, RecordType::A),
+        (domain, RecordType::AAAA),
+        (domain, RecordType::MX),
+        (domain, RecordType::NS),
+        (domain, RecordType::SOA),
+        (domain, RecordType::TXT),
+        (domain, RecordType::CAA),
+        (domain, RecordType::DNSKEY),
+        (&www_domain, RecordType::A),
+        (&www_domain, RecordType::AAAA)
+    ];
+
+    let mut report: TtlReport = TtlReport {
+        nameserver: String::new(),
+        parent_nameserver: String::new(),
+        records: Vec::new(),
+        child_ns_ttl: None,
+        parent_ns_ttl: None,
+        dnskey_ttl: None,
+        ds_ttl: None,
+        findings: Vec::new(),
+    };
+
+    let server: IpAddr = first_responding(&servers, domain).ok_or_else(||
+        Error::msg(format!("No authoritative nameserver answered for {}", domain))
+    )?;
+    report.nameserver = server.to_string();
+
+    for (name, record_type) in rrsets {
+        let response: Message = match query(server, name, record_type) {
+            Ok(response) => response,
+            Err(_e) => {
+                continue;
+            }
+        };
+
+        let ttls: Vec<u32> = response
+            .answers()
+            .iter()
+            .filter(|record: &&Record| record.record_type() == record_type)
+            .map(|record: &Record| record.ttl())
+            .collect();
+
+        if let Some(ttl_record) = evaluate_rrset(name, record_type, &ttls, &mut report.findings) {
+            match record_type {
+                RecordType::NS if name == domain => {
+                    report.child_ns_ttl = Some(ttl_record.ttl);
+                }
+                RecordType::DNSKEY => {
+                    report.dnskey_ttl = Some(ttl_record.ttl);
+                }
+                _ => {}
+            }
+
+            report.records.push(ttl_record);
+        }
+    }
+
+    if let Some((parent, parent_server)) = parent_zone_server(&resolver, domain) {
+        report.parent_nameserver = format!("{} ({})", parent, parent_server);
+
+        if let Ok(response) = query(parent_server, domain, RecordType::NS) {
+            // A delegation is answered with a referral, so the NS set lives in the authority section
+            report.parent_ns_ttl = response
+                .answers()
+                .iter()
+                .chain(response.name_servers().iter())
+                .find(|record: &&Record| record.record_type() == RecordType::NS)
+                .map(|record: &Record| record.ttl());
+        }
+
+        if let Ok(response) = query(parent_server, domain, RecordType::DS) {
+            let ttls: Vec<u32> = response
+                .answers()
+                .iter()
+                .filter(|record: &&Record| record.record_type() == RecordType::DS)
+                .map(|record: &Record| record.ttl())
+                .collect();
+
+            if let Some(ttl_record) = evaluate_rrset(domain, RecordType::DS, &ttls, &mut report.findings) {
+                report.ds_ttl = Some(ttl_record.ttl);
+                report.records.push(ttl_record);
+            }
+        }
+    }
+
+    if let (Some(parent), Some(child)) = (report.parent_ns_ttl, report.child_ns_ttl) {
+        if parent != child {
+            report.findings.push(TtlFinding {
+                name: domain.to_string(),
+                record_type: "NS".to_string(),
+                issue: "parent_child_mismatch".to_string(),
+                ttl: child,
+                message: format!("NS TTL is {} in the parent zone but {} in the child zone", parent, child),
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+fn evaluate_rrset(
+    name: &str,
+    record_type: RecordType,
+    ttls: &[u32],
+    findings: &mut Vec<TtlFinding>
+) -> Option<TtlRecord> {
+    let ttl: u32 = *ttls.iter().min()?;
+    let policy: TtlPolicy = TtlPolicy::for_type(record_type);
+    let consistent: bool = ttls.iter().all(|t: &u32| *t == ttl);
+
+    let mut finding = |issue: &str, message: String| {
+        findings.push(TtlFinding {
+            name: name.to_string(),
+            record_type: record_type.to_string(),
+            issue: issue.to_string(),
+            ttl,
+            message,
+        });
+    };
+
+    if !consistent {
+        let mut distinct: Vec<u32> = ttls.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        finding("inconsistent", format!("{} RRset has differing TTLs: {:?}", record_type, distinct));
+    }
+
+    if ttl < policy.min {
+        finding("low", format!("{} TTL {} is below the minimum of {}", record_type, ttl, policy.min));
+    }
+
+    if ttl > policy.max {
+        if record_type == RecordType::DNSKEY {
+            finding(
+                "rollover",
+                format!(
+                    "DNSKEY TTL {} exceeds {}, resolvers keep retired keys that long during a rollover",
+                    ttl,
+                    policy.max
+                )
+            );
+        } else {
+            finding("high", format!("{} TTL {} is above the maximum of {}", record_type, ttl, policy.max));
+        }
+    }
+
+    Some(TtlRecord {
+        name: name.to_string(),
+        record_type: record_type.to_string(),
+        ttl,
+        consistent,
+        min_ttl: policy.min,
+        max_ttl: policy.max,
+    })
+}
+
+fn first_responding(servers: &[IpAddr], domain: &str) -> Option<IpAddr> {
+    servers
+        .iter()
+        .find(|server: &&IpAddr| query(**server, domain, RecordType::SOA).is_ok())
+        .copied()
+}
+
+fn parent_zone_server(resolver: &Resolver, domain: &str) -> Option<(String, IpAddr)> {
+    let parent: &str = domain.trim_end_matches('.').split_once('.')?.1;
+    let servers: Vec<IpAddr> = nameserver_addresses(resolver, parent).ok()?;
+    let server: IpAddr = first_responding(&servers, parent)?;
+
+    Some((parent.to_string(), server))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ evaluate_rrset, TtlPolicy };
+    use crate::types::TtlFinding;
+
+    use std::env::{ remove_var, set_var };
+    use std::sync::Mutex;
+
+    use hickory_resolver::proto::rr::RecordType;
+
+    // Thresholds come from the environment, so tests that read them must not overlap
+    static ENV: Mutex<()> = Mutex::new(());
+
+    fn issues(findings: &[TtlFinding]) -> Vec<&str> {
+        findings.iter().map(|finding: &TtlFinding| finding.issue.as_str()).collect()
+    }
+
+    #[test]
+    fn default_thresholds_per_type() {
+        let _env = ENV.lock().unwrap();
+
+        for (record_type, min, max) in [
+            (RecordType::A, 300, 86400),
+            (RecordType::NS, 3600, 172800),
+            (RecordType::DS, 3600, 172800),
+            (RecordType::SOA, 3600, 86400),
+            (RecordType::DNSKEY, 3600, 86400),
+        ] {
+            let policy: TtlPolicy = TtlPolicy::for_type(record_type);
+            assert_eq!((policy.min, policy.max), (min, max), "{}", record_type);
+        }
+    }
+
+    #[test]
+    fn environment_overrides() {
+        let _env = ENV.lock().unwrap();
+
+        set_var("TTL_MIN", "60");
+        set_var("TTL_MAX", "604800");
+        set_var("TTL_MIN_MX", "900");
+        set_var("TTL_MAX_MX", " 7200 ");
+        set_var("TTL_MAX_TXT", "not a number");
+
+        let mx: TtlPolicy = TtlPolicy::for_type(RecordType::MX);
+        let txt: TtlPolicy = TtlPolicy::for_type(RecordType::TXT);
+        let ns: TtlPolicy = TtlPolicy::for_type(RecordType::NS);
+
+        for key in ["TTL_MIN", "TTL_MAX", "TTL_MIN_MX", "TTL_MAX_MX", "TTL_MAX_TXT"] {
+            remove_var(key);
+        }
+
+        assert_eq!((mx.min, mx.max), (900, 7200));
+        assert_eq!((txt.min, txt.max), (60, 604800));
+        assert_eq!((ns.min, ns.max), (60, 604800));
+    }
+
+    #[test]
+    fn flags_thresholds() {
+        let _env = ENV.lock().unwrap();
+        let mut findings: Vec<TtlFinding> = Vec::new();
+
+        let record = evaluate_rrset("example.test", RecordType::A, &[3600], &mut findings).unwrap();
+        assert!(record.consistent);
+        assert_eq!((record.ttl, record.min_ttl, record.max_ttl), (3600, 300, 86400));
+        assert!(findings.is_empty());
+
+        evaluate_rrset("example.test", RecordType::A, &[60], &mut findings);
+        evaluate_rrset("example.test", RecordType::MX, &[604800], &mut findings);
+        evaluate_rrset("example.test", RecordType::DNSKEY, &[172800], &mut findings);
+        assert_eq!(issues(&findings), vec!["low", "high", "rollover"]);
+        assert_eq!(findings[0].message, "A TTL 60 is below the minimum of 300");
+
+        assert!(evaluate_rrset("example.test", RecordType::A, &[], &mut findings).is_none());
+    }
+
+    #[test]
+    fn flags_inconsistent_rrset() {
+        let _env = ENV.lock().unwrap();
+        let mut findings: Vec<TtlFinding> = Vec::new();
+
+        let record = evaluate_rrset("example.test", RecordType::NS, &[7200, 3600, 7200], &mut findings).unwrap();
+
+        assert!(!record.consistent);
+        assert_eq!(record.ttl, 3600);
+        assert_eq!(issues(&findings), vec!["inconsistent"]);
+        assert_eq!(findings[0].message, "NS RRset has differing TTLs: [3600, 7200]");
+    }
+}
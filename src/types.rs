@@ -58,6 +58,26 @@ pub struct NSAddresses {
     pub recursive: bool,
     pub udp: bool,
     pub tcp: bool,
+    pub cookies: bool,
+    pub cookies_stable: bool,
+    pub badcookie_handled: bool,
+    pub encrypted_transports: Vec<EncryptedTransport>,
+}
+
+#[doc = "DNS over TLS or DNS over HTTPS offered by a nameserver address"]
+#[derive(SimpleObject, Debug)]
+pub struct EncryptedTransport {
+    pub protocol: String,
+    pub port: u16,
+    pub path: String,
+    pub offered: bool,
+    pub certificate_subject: String,
+    pub certificate_not_before: String,
+    pub certificate_not_after: String,
+    pub certificate_valid: bool,
+    pub certificate_error: String,
+    pub query_succeeded: bool,
+    pub error: String,
 }
 
 #[doc = "SOA Record information for a domain"]
@@ -104,14 +124,520 @@ pub struct Mx {
     pub name: String,
     pub ipv4: String,
     pub ipv6: String,
+    pub ipv4_addresses: Vec<String>,
+    pub ipv6_addresses: Vec<String>,
     pub ptr: String,
-    pub preference: i8,
+    pub preference: u16,
     pub dnssec: bool,
+    pub smtp: Vec<SmtpProbe>,
+    pub relay: Vec<RelayTest>,
+    pub tls: Option<TlsScan>,
+    pub certificate: Option<CertificateChain>,
+    pub dane: Dane,
+    pub cname: String,
+    pub ip_literal: bool,
+    pub exists: bool,
+    pub duplicate: bool,
+    pub errors: Vec<String>,
 }
+#[doc = "RFC 7505 null MX: the domain explicitly accepts no mail"]
+#[derive(SimpleObject)]
+pub struct NullMx {
+    pub present: bool,
+    pub valid: bool,
+    pub spf_fail_all: bool,
+    pub dmarc_reject: bool,
+    pub no_dkim: bool,
+    pub recommended_posture: bool,
+    pub errors: Vec<String>,
+}
+
 #[derive(SimpleObject)]
 pub struct Email {
+    pub null_mx: NullMx,
     pub mx: Vec<Mx>,
+    pub mta_sts: MtaSts,
+    pub tls_rpt: TlsRpt,
+    pub spf: Spf,
+    pub dmarc: Dmarc,
+    pub dkim: Dkim,
+    pub bimi: Bimi,
+}
+#[doc = "TTL of an RRset as served by the authoritative nameserver"]
+#[derive(SimpleObject)]
+pub struct TtlRecord {
+    pub name: String,
+    pub record_type: String,
+    pub ttl: u32,
+    pub consistent: bool,
+    pub min_ttl: u32,
+    pub max_ttl: u32,
+}
+
+#[doc = "TTL policy violation for an RRset"]
+#[derive(SimpleObject)]
+pub struct TtlFinding {
+    pub name: String,
+    pub record_type: String,
+    pub issue: String,
+    pub ttl: u32,
+    pub message: String,
+}
+
+#[doc = "TTL policy analysis across the zone and its delegation"]
+#[derive(SimpleObject)]
+pub struct TtlReport {
+    pub nameserver: String,
+    pub parent_nameserver: String,
+    pub records: Vec<TtlRecord>,
+    pub child_ns_ttl: Option<u32>,
+    pub parent_ns_ttl: Option<u32>,
+    pub dnskey_ttl: Option<u32>,
+    pub ds_ttl: Option<u32>,
+    pub findings: Vec<TtlFinding>,
+}
+
+#[doc = "Forward-confirmed reverse DNS for an address of the domain, with every source and host it was found under"]
+#[derive(SimpleObject)]
+pub struct ReverseDns {
+    pub sources: Vec<String>,
+    pub hosts: Vec<String>,
+    pub ip: String,
+    pub ptr: String,
+    pub forward_addresses: Vec<String>,
+    pub confirmed: bool,
+    pub error: String,
+}
+
+#[doc = "IPv6 address of the domain and whether it answers; port is the service port that answered, or the first one tried"]
+#[derive(SimpleObject)]
+pub struct Ipv6Address {
+    pub source: String,
+    pub host: String,
+    pub ip: String,
+    pub port: u16,
+    pub reachable: bool,
+    pub ptr: String,
+    pub ptr_present: bool,
+    pub error: String,
+}
+
+#[doc = "IPv6 readiness summary for a domain"]
+#[derive(SimpleObject)]
+pub struct Ipv6Readiness {
+    pub apex_aaaa: bool,
+    pub www_aaaa: bool,
+    pub ns_ipv6_reachable: u32,
+    pub ns_ipv6_sufficient: bool,
+    pub mx_aaaa: bool,
+    pub all_reachable: bool,
+    pub ptr_present: bool,
+    pub ready: bool,
+    pub addresses: Vec<Ipv6Address>,
+}
+
+#[doc = "Response size of a single query against an authoritative nameserver"]
+#[derive(SimpleObject)]
+pub struct AmplificationQuery {
+    pub record_type: String,
+    pub request_size: u32,
+    pub response_size: u32,
+    pub factor: f64,
+    pub truncated: bool,
+    pub error: String,
+}
+
+#[doc = "Amplification risk of a single authoritative nameserver address"]
+#[derive(SimpleObject)]
+pub struct AmplificationServer {
+    pub ip: String,
+    pub queries: Vec<AmplificationQuery>,
+    pub max_factor: f64,
+    pub any_minimal: bool,
+    pub burst_sent: u32,
+    pub burst_answered: u32,
+    pub burst_truncated: u32,
+    pub rrl_active: bool,
+}
+
+#[doc = "DNS amplification and response-size risk for a domain"]
+#[derive(SimpleObject)]
+pub struct Amplification {
+    pub max_factor: f64,
+    pub any_minimal: bool,
+    pub rrl_active: bool,
+    pub servers: Vec<AmplificationServer>,
+}
+
+#[doc = "SMTP session on port 25 of a single mail server address"]
+#[derive(SimpleObject)]
+pub struct SmtpProbe {
+    pub ip: String,
+    pub port: u16,
+    pub reachable: bool,
+    pub banner: String,
+    pub greeting_hostname: String,
+    pub ehlo_extensions: Vec<String>,
+    pub starttls_advertised: bool,
+    pub starttls_negotiated: bool,
+    pub tls_version: String,
+    pub tls_cipher: String,
+    pub error: String,
+}
+
+#[doc = "Open relay attempt: MAIL FROM and RCPT TO with third party addresses, reset before DATA"]
+#[derive(SimpleObject)]
+pub struct RelayTest {
+    pub ip: String,
+    pub sender: String,
+    pub recipient: String,
+    pub starttls: bool,
+    pub mail_from_reply: String,
+    pub rcpt_to_reply: String,
+    pub open_relay: bool,
+    pub error: String,
+}
+
+#[doc = "TLS protocol version offered by a server, graded per the NCSC TLS guidelines"]
+#[derive(SimpleObject)]
+pub struct TlsVersionSupport {
+    pub version: String,
+    pub supported: bool,
+    pub grade: String,
+}
+
+#[doc = "Cipher suite accepted by a server, in the order the server picked it"]
+#[derive(SimpleObject)]
+pub struct TlsCipher {
+    pub name: String,
+    pub standard_name: String,
+    pub version: String,
+    pub grade: String,
+}
+
+#[doc = "Ephemeral key exchange of a TLS server; DHE below 2048 bits, weak well-known primes and ECDHE below 256 bits are insufficient"]
+#[derive(SimpleObject)]
+pub struct KeyExchange {
+    pub negotiated_group: String,
+    pub negotiated_bits: u32,
+    pub dhe_supported: bool,
+    pub dhe_bits: u32,
+    pub dhe_prime: String,
+    pub dhe_prime_weak: bool,
+    pub dhe_sufficient: bool,
+    pub ecdhe_supported: bool,
+    pub ecdhe_curve: String,
+    pub ecdhe_bits: u32,
+    pub ecdhe_sufficient: bool,
+    pub error: String,
+}
+
+#[doc = "Supported TLS versions and cipher suites of a server address; grade is the worst of all of them"]
+#[derive(SimpleObject)]
+pub struct TlsScan {
+    pub ip: String,
+    pub versions: Vec<TlsVersionSupport>,
+    pub ciphers: Vec<TlsCipher>,
+    pub server_preference: bool,
+    pub key_exchange: KeyExchange,
+    pub grade: String,
+    pub error: String,
+}
+
+#[doc = "X.509 certificate as presented by a server"]
+#[derive(SimpleObject)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    pub sans: Vec<String>,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub days_until_expiry: i64,
+    pub key_type: String,
+    pub key_bits: u32,
+    pub signature_algorithm: String,
+    pub fingerprint_sha256: String,
+}
+
+#[doc = "Certificate chain of a server address, validated against the trust store and the expected hostname"]
+#[derive(SimpleObject)]
+pub struct CertificateChain {
+    pub ip: String,
+    pub certificates: Vec<Certificate>,
+    pub trusted: bool,
+    pub verify_error: String,
+    pub hostname_match: bool,
+    pub days_until_expiry: i64,
+    pub error: String,
+}
+
+#[doc = "TLSA record of an MX host and whether it matches the presented certificate chain; matches is unset when no chain was collected"]
+#[derive(SimpleObject)]
+pub struct TlsaRecord {
+    pub usage: u8,
+    pub selector: u8,
+    pub matching_type: u8,
+    pub data: String,
+    pub usable: bool,
+    pub matches: Option<bool>,
 }
+
+#[doc = "DANE for SMTP of an MX host: status is absent, valid, mismatched, insecure, unverified (no STARTTLS chain to match, see smtpProbe) or error"]
+#[derive(SimpleObject)]
+pub struct Dane {
+    pub name: String,
+    pub status: String,
+    pub dnssec: bool,
+    pub records: Vec<TlsaRecord>,
+    pub rollover: bool,
+    pub error: String,
+}
+
+#[doc = "MTA-STS TXT record and policy; enforced when a valid enforce policy covers every MX over a trusted certificate"]
+#[derive(SimpleObject)]
+pub struct MtaSts {
+    pub records: Vec<String>,
+    pub record_valid: bool,
+    pub id: String,
+    pub policy_url: String,
+    pub policy_fetched: bool,
+    pub http_status: u16,
+    pub content_type: String,
+    pub version: String,
+    pub mode: String,
+    pub max_age: u64,
+    pub mx_patterns: Vec<String>,
+    pub policy_valid: bool,
+    pub policy_errors: Vec<String>,
+    pub certificate: Option<CertificateChain>,
+    pub certificate_valid: bool,
+    pub key_exchange: Option<KeyExchange>,
+    pub mx_matched: bool,
+    pub unmatched_mx: Vec<String>,
+    pub enforced: bool,
+    pub error: String,
+}
+
+#[doc = "Report destination from a rua or ruf tag"]
+#[derive(SimpleObject)]
+pub struct ReportUri {
+    pub uri: String,
+    pub scheme: String,
+    pub valid: bool,
+    pub error: String,
+}
+
+#[doc = "SMTP TLS Reporting record at _smtp._tls (RFC 8460)"]
+#[derive(SimpleObject)]
+pub struct TlsRpt {
+    pub records: Vec<String>,
+    pub valid: bool,
+    pub multiple_records: bool,
+    pub rua: Vec<ReportUri>,
+    pub duplicate_rua: Vec<String>,
+    pub errors: Vec<String>,
+    pub error: String,
+}
+
+#[doc = "BIMI record at default._bimi with its SVG Tiny PS logo; declined when l= and a= are both empty"]
+#[derive(SimpleObject)]
+pub struct Bimi {
+    pub name: String,
+    pub records: Vec<String>,
+    pub multiple_records: bool,
+    pub record_valid: bool,
+    pub declined: bool,
+    pub logo_url: String,
+    pub authority_url: String,
+    pub logo_fetched: bool,
+    pub content_type: String,
+    pub logo_size: u32,
+    pub svg_valid: bool,
+    pub svg_errors: Vec<String>,
+    pub certificate: Option<CertificateChain>,
+    pub certificate_valid: bool,
+    pub key_exchange: Option<KeyExchange>,
+    pub dmarc_enforced: bool,
+    pub valid: bool,
+    pub errors: Vec<String>,
+    pub error: String,
+}
+
+#[doc = "DKIM key record at <selector>._domainkey; weak is an RSA key below 2048 bits"]
+#[derive(SimpleObject)]
+pub struct DkimKey {
+    pub selector: String,
+    pub name: String,
+    pub record: String,
+    pub key_type: String,
+    pub hash_algorithms: Vec<String>,
+    pub service_types: Vec<String>,
+    pub flags: Vec<String>,
+    pub algorithm: String,
+    pub key_bits: u32,
+    pub revoked: bool,
+    pub testing: bool,
+    pub weak: bool,
+    pub valid: bool,
+    pub errors: Vec<String>,
+}
+
+#[doc = "DKIM keys found by probing selectors"]
+#[derive(SimpleObject)]
+pub struct Dkim {
+    pub selectors_checked: Vec<String>,
+    pub keys: Vec<DkimKey>,
+    pub valid: bool,
+    pub errors: Vec<String>,
+    pub error: String,
+}
+
+#[doc = "DMARC report URI outside the organizational domain and whether its destination accepts the reports"]
+#[derive(SimpleObject)]
+pub struct ExternalReport {
+    pub uri: String,
+    pub destination: String,
+    pub name: String,
+    pub authorized: bool,
+    pub error: String,
+}
+
+#[doc = "DMARC record (RFC 7489); name is where it was found, inherited when that is the organizational domain"]
+#[derive(SimpleObject)]
+pub struct Dmarc {
+    pub name: String,
+    pub organizational_domain: String,
+    pub inherited: bool,
+    pub records: Vec<String>,
+    pub multiple_records: bool,
+    pub valid: bool,
+    pub policy: String,
+    pub subdomain_policy: String,
+    pub non_existent_policy: String,
+    pub effective_policy: String,
+    pub pct: u32,
+    pub adkim: String,
+    pub aspf: String,
+    pub rua: Vec<ReportUri>,
+    pub ruf: Vec<ReportUri>,
+    pub fo: Vec<String>,
+    pub ri: u32,
+    pub external_reports: Vec<ExternalReport>,
+    pub enforced: bool,
+    pub errors: Vec<String>,
+    pub error: String,
+}
+
+#[doc = "Record reached through an SPF include or redirect"]
+#[derive(SimpleObject)]
+pub struct SpfInclude {
+    pub domain: String,
+    pub mechanism: String,
+    pub record: String,
+}
+
+#[doc = "SPF record with everything it includes expanded; ip4 and ip6 are the flattened authorised ranges"]
+#[derive(SimpleObject)]
+pub struct Spf {
+    pub records: Vec<String>,
+    pub record: String,
+    pub multiple_records: bool,
+    pub valid: bool,
+    pub permerror: bool,
+    pub temperror: bool,
+    pub all: String,
+    pub weak_all: bool,
+    pub dns_lookups: u32,
+    pub void_lookups: u32,
+    pub lookup_limit_exceeded: bool,
+    pub void_limit_exceeded: bool,
+    pub includes: Vec<SpfInclude>,
+    pub ip4: Vec<String>,
+    pub ip6: Vec<String>,
+    pub unexpanded: Vec<String>,
+    pub errors: Vec<String>,
+    pub error: String,
+}
+
+#[doc = "RFC 7208 check_host() result for one client, with the matching mechanism and the evaluation steps"]
+#[derive(SimpleObject)]
+pub struct SpfEvaluation {
+    pub domain: String,
+    pub ip: String,
+    pub sender: String,
+    pub helo: String,
+    pub result: String,
+    pub mechanism: String,
+    pub explanation: String,
+    pub dns_lookups: u32,
+    pub void_lookups: u32,
+    pub trace: Vec<String>,
+}
+
+#[doc = "Mail submission or retrieval endpoint, found through SRV (RFC 6186) or a conventional hostname"]
+#[derive(SimpleObject)]
+pub struct MailEndpoint {
+    pub service: String,
+    pub protocol: String,
+    pub host: String,
+    pub port: u16,
+    pub source: String,
+    pub implicit_tls: bool,
+    pub ip: String,
+    pub reachable: bool,
+    pub tls_negotiated: bool,
+    pub tls_version: String,
+    pub certificate: Option<CertificateChain>,
+    pub certificate_valid: bool,
+    pub key_exchange: Option<KeyExchange>,
+    pub plaintext_auth_before_tls: bool,
+    pub error: String,
+}
+
+#[doc = "Mail user agent endpoints; unavailable lists SRV names with a \".\" target"]
+#[derive(SimpleObject)]
+pub struct MailClients {
+    pub endpoints: Vec<MailEndpoint>,
+    pub unavailable: Vec<String>,
+    pub plaintext_auth_before_tls: bool,
+}
+
+#[doc = "Mail server advertised by an autoconfig or Autodiscover document; plaintext when its socket type is plain"]
+#[derive(SimpleObject)]
+pub struct AutoconfigServer {
+    pub source: String,
+    pub protocol: String,
+    pub hostname: String,
+    pub port: u16,
+    pub socket_type: String,
+    pub authentication: Vec<String>,
+    pub plaintext: bool,
+}
+
+#[doc = "Autoconfig or Autodiscover document fetch"]
+#[derive(SimpleObject)]
+pub struct AutoconfigDocument {
+    pub url: String,
+    pub https: bool,
+    pub fetched: bool,
+    pub http_status: u16,
+    pub certificate: Option<CertificateChain>,
+    pub certificate_valid: bool,
+    pub key_exchange: Option<KeyExchange>,
+    pub error: String,
+}
+
+#[doc = "Mail client autoconfiguration: Thunderbird autoconfig and Outlook Autodiscover; plaintext when a server is plain or a document came over HTTP"]
+#[derive(SimpleObject)]
+pub struct Autoconfig {
+    pub srv_records: Vec<String>,
+    pub documents: Vec<AutoconfigDocument>,
+    pub servers: Vec<AutoconfigServer>,
+    pub plaintext: bool,
+    pub error: String,
+}
+
 #[derive(Default)]
 pub struct QueryRoot;
 